@@ -0,0 +1,214 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+use crate::amount::{self, Amount};
+use crate::denom::DenomStrategy;
+use crate::error::SimError;
+use crate::network::Network;
+use crate::parse::number;
+use crate::staker::Staker;
+use crate::wallet::Consolidate;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// How a staker plays the protocol.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum Behavior {
+    #[default]
+    Honest,
+    /// Keeps every mature output split into small denoms to get the most
+    /// kernel chances out of its coins.
+    Splitter,
+    /// Withholds the blocks it finds and publishes them to orphan honest
+    /// blocks. `gamma` is the share of the network that builds on its block
+    /// when both are racing.
+    Selfish { gamma: f64 },
+    /// Leaves loose outputs alone until a locked stake input unlocks, then
+    /// consolidates everything in one go.
+    ConsolidationTimer,
+}
+
+impl Behavior {
+    pub fn is_honest(&self) -> bool {
+        *self == Behavior::Honest
+    }
+
+    pub fn is_selfish(&self) -> bool {
+        matches!(self, Behavior::Selfish { .. })
+    }
+
+    pub fn consolidate(&self) -> Consolidate {
+        match self {
+            Behavior::Honest | Behavior::Selfish { .. } => Consolidate::Always,
+            Behavior::Splitter => Consolidate::Everything,
+            Behavior::ConsolidationTimer => Consolidate::OnUnlock,
+        }
+    }
+
+    /// The denom strategy the behavior starts out with.
+    pub fn strategy(&self) -> DenomStrategy {
+        match self {
+            Behavior::Splitter => DenomStrategy::OnlySmall,
+            _ => DenomStrategy::Optimal,
+        }
+    }
+}
+
+/// An adversarial staker to add to the network.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdversarySpec {
+    pub behavior: Behavior,
+    pub balance: Amount,
+}
+
+impl AdversarySpec {
+    /// Adds the adversary as a new staker with an average computer and returns
+    /// its id.
+    pub fn apply(&self, network: &mut Network) -> Result<u64, SimError> {
+        let id = network.add_staker(self.balance, self.behavior.strategy(), 0.0)?;
+        if let Some(staker) = network.staker_mut(id) {
+            staker.set_behavior(self.behavior);
+        }
+        Ok(id)
+    }
+}
+
+/// Parses `splitter:COINS`, `selfish:COINS:GAMMA` or `timer:COINS`.
+impl FromStr for AdversarySpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let (behavior, coins) = match parts.as_slice() {
+            ["splitter", coins] => (Behavior::Splitter, coins),
+            ["timer", coins] => (Behavior::ConsolidationTimer, coins),
+            ["selfish", coins, gamma] => {
+                let gamma: f64 = number(gamma)?;
+                if !(0.0..=1.0).contains(&gamma) {
+                    return Err(format!("Gamma must be between 0 and 1, got {}.", gamma));
+                }
+                (Behavior::Selfish { gamma }, coins)
+            }
+            _ => {
+                return Err(format!(
+                    "Expected splitter:COINS, selfish:COINS:GAMMA or timer:COINS, got {}.",
+                    s
+                ));
+            }
+        };
+        let balance: Amount = coins.parse()?;
+        if balance.is_zero() {
+            return Err("An adversary needs some coins.".to_string());
+        }
+
+        Ok(Self { behavior, balance })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdversaryRow {
+    pub id: u64,
+    pub behavior: Behavior,
+    pub percent_total: f64,
+    pub rewards_earned: Amount,
+    pub fair_rewards: f64,
+    pub orphaned_count: u64,
+    /// Rewards earned over the fair share, in percent.
+    pub excess_pct: f64,
+}
+
+/// Compares what each non-honest staker earned with what its share of the
+/// staked coins would have paid.
+#[derive(Debug, Serialize)]
+pub struct AdversaryReport {
+    pub rows: Vec<AdversaryRow>,
+}
+
+impl AdversaryReport {
+    pub fn from_stakers(stakers: &[Staker]) -> Self {
+        let rows = stakers
+            .iter()
+            .filter(|p| !p.behavior().is_honest())
+            .map(|p| AdversaryRow {
+                id: p.id(),
+                behavior: p.behavior(),
+                percent_total: p.percent_total(),
+                rewards_earned: p.rewards_earned(),
+                fair_rewards: p.fair_rewards(),
+                orphaned_count: p.orphaned_count(),
+                excess_pct: if p.fair_rewards() > 0.0 {
+                    (p.rewards_earned().to_sat() as f64 / p.fair_rewards() - 1.0) * 100.0
+                } else {
+                    0.0
+                },
+            })
+            .collect();
+
+        Self { rows }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+impl fmt::Display for AdversaryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>6} {:<28} {:>8} {:>14} {:>14} {:>9} {:>9}",
+            "id", "behavior", "% total", "earned", "fair", "orphaned", "excess %"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:>6} {:<28} {:>8.4} {:>14} {:>14.0} {:>9} {:>9.2}",
+                row.id,
+                format!("{:?}", row.behavior),
+                row.percent_total * 100.0,
+                row.rewards_earned.to_sat() / amount::COIN,
+                row.fair_rewards / amount::COIN as f64,
+                row.orphaned_count,
+                row.excess_pct
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec() {
+        let spec: AdversarySpec = "selfish:1_000:0.5".parse().unwrap();
+        assert_eq!(spec.behavior, Behavior::Selfish { gamma: 0.5 });
+        assert_eq!(spec.balance, Amount::from_coins(1_000));
+        assert!("splitter:10".parse::<AdversarySpec>().is_ok());
+        assert!("timer:0".parse::<AdversarySpec>().is_err());
+        assert!("selfish:10:2".parse::<AdversarySpec>().is_err());
+        assert!("lazy:10".parse::<AdversarySpec>().is_err());
+    }
+}
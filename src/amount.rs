@@ -21,13 +21,277 @@
 // CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
+use crate::error::SimError;
+use serde::Serialize;
+use serde::de::{self, Deserialize, Deserializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
+use std::str::FromStr;
 
-pub type Amount = u64;
+pub static COIN: u64 = Amount::COIN.to_sat();
+pub static CENT: u64 = Amount::CENT.to_sat();
+pub static MAX_MONEY: u64 = Amount::MAX_MONEY.to_sat();
 
-pub static COIN: Amount = 100_000_000;
-pub static CENT: Amount = 100_000;
-pub static MAX_MONEY: Amount = 300_000_000 * COIN;
-
-pub fn money_range(value: Amount) -> bool {
+pub fn money_range(value: u64) -> bool {
     value <= MAX_MONEY
 }
+
+/// A VEIL amount in satoshis. Serializes as the bare number of satoshis and
+/// refuses to deserialize one outside the money range.
+///
+/// `+`, `-` and `*` panic if the result would leave `0..=MAX_MONEY`, the
+/// checked and saturating variants don't.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const CENT: Amount = Amount(100_000);
+    pub const COIN: Amount = Amount(100_000_000);
+    pub const MAX_MONEY: Amount = Amount(300_000_000 * 100_000_000);
+
+    /// For constants known to be in range. Use `try_from_sat` for anything
+    /// computed.
+    pub const fn from_sat(sats: u64) -> Self {
+        debug_assert!(sats <= Self::MAX_MONEY.0);
+        Amount(sats)
+    }
+
+    pub fn try_from_sat(sats: u64) -> Result<Self, SimError> {
+        SimError::check_range(sats).map(Amount)
+    }
+
+    pub const fn from_coins(coins: u64) -> Self {
+        Amount(coins * Self::COIN.0)
+    }
+
+    pub const fn to_sat(self) -> u64 {
+        self.0
+    }
+
+    pub fn to_coins(self) -> f64 {
+        self.0 as f64 / Self::COIN.0 as f64
+    }
+
+    /// `fraction` of the amount rounded down, with `fraction` clamped to
+    /// `0..=1`.
+    pub fn fraction(self, fraction: f64) -> Amount {
+        Amount(((self.0 as f64 * fraction.clamp(0.0, 1.0)) as u64).min(self.0))
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0
+            .checked_add(other.0)
+            .filter(|p| money_range(*p))
+            .map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn checked_mul(self, times: u64) -> Option<Amount> {
+        self.0
+            .checked_mul(times)
+            .filter(|p| money_range(*p))
+            .map(Amount)
+    }
+
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        self.checked_add(other).unwrap_or(Self::MAX_MONEY)
+    }
+
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Add for Amount {
+    type Output = Amount;
+
+    fn add(self, other: Amount) -> Amount {
+        self.checked_add(other)
+            .unwrap_or_else(|| panic!("{} + {} is out of range.", self, other))
+    }
+}
+
+impl AddAssign for Amount {
+    fn add_assign(&mut self, other: Amount) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, other: Amount) -> Amount {
+        self.checked_sub(other)
+            .unwrap_or_else(|| panic!("{} - {} is negative.", self, other))
+    }
+}
+
+impl SubAssign for Amount {
+    fn sub_assign(&mut self, other: Amount) {
+        *self = *self - other;
+    }
+}
+
+impl Mul<u64> for Amount {
+    type Output = Amount;
+
+    fn mul(self, times: u64) -> Amount {
+        self.checked_mul(times)
+            .unwrap_or_else(|| panic!("{} * {} is out of range.", self, times))
+    }
+}
+
+impl Div<u64> for Amount {
+    type Output = Amount;
+
+    fn div(self, divisor: u64) -> Amount {
+        Amount(self.0 / divisor)
+    }
+}
+
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+        iter.fold(Amount::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Amount> for Amount {
+    fn sum<I: Iterator<Item = &'a Amount>>(iter: I) -> Amount {
+        iter.copied().sum()
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let sats = u64::deserialize(deserializer)?;
+        Amount::try_from_sat(sats).map_err(de::Error::custom)
+    }
+}
+
+/// Coins with up to eight decimals, or rounded to the given precision.
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let whole = self.0 / COIN;
+        let fraction = self.0 % COIN;
+        match f.precision() {
+            Some(precision) => write!(f, "{:.*} VEIL", precision, self.to_coins()),
+            None if fraction == 0 => write!(f, "{} VEIL", whole),
+            None => write!(
+                f,
+                "{}.{} VEIL",
+                whole,
+                format!("{:08}", fraction).trim_end_matches('0')
+            ),
+        }
+    }
+}
+
+/// Parses coins like `12.5` or `12.5 VEIL`, or satoshis like `1250000000sat`.
+/// Underscores are ignored.
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.replace('_', "");
+        let value = value.trim();
+        let invalid = || format!("Invalid amount {}.", s);
+        let sats = match value.strip_suffix("sat") {
+            Some(sats) => sats.trim().parse::<u64>().map_err(|_| invalid())?,
+            None => {
+                let coins = value.strip_suffix("VEIL").unwrap_or(value).trim();
+                let (whole, fraction) = match coins.split_once('.') {
+                    Some((whole, fraction)) => (whole, fraction),
+                    None => (coins, ""),
+                };
+                let digits = |p: &str| p.chars().all(|c| c.is_ascii_digit());
+                if whole.is_empty() || fraction.len() > 8 || !digits(whole) || !digits(fraction) {
+                    return Err(invalid());
+                }
+                let whole: u64 = whole.parse().map_err(|_| invalid())?;
+                let fraction: u64 = format!("{:0<8}", fraction).parse().map_err(|_| invalid())?;
+                whole
+                    .checked_mul(COIN)
+                    .and_then(|p| p.checked_add(fraction))
+                    .ok_or_else(invalid)?
+            }
+        };
+        Amount::try_from_sat(sats).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        let amount: Amount = "12.5".parse().unwrap();
+        assert_eq!(amount, Amount::from_sat(1_250_000_000));
+        assert_eq!("1_250_000_000sat".parse::<Amount>().unwrap(), amount);
+        assert_eq!(amount.to_string(), "12.5 VEIL");
+        assert_eq!(amount.to_string().parse::<Amount>().unwrap(), amount);
+        assert_eq!(format!("{:.2}", amount), "12.50 VEIL");
+        assert_eq!(Amount::from_coins(50).to_string(), "50 VEIL");
+        assert_eq!("0.00000001".parse::<Amount>().unwrap(), Amount::from_sat(1));
+
+        assert!("0.000000001".parse::<Amount>().is_err());
+        assert!("-1".parse::<Amount>().is_err());
+        assert!(".5".parse::<Amount>().is_err());
+        assert!("300000001".parse::<Amount>().is_err());
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let coin = Amount::COIN;
+        assert_eq!(coin.checked_sub(coin * 2), None);
+        assert_eq!(Amount::MAX_MONEY.checked_add(Amount::from_sat(1)), None);
+        assert_eq!(Amount::MAX_MONEY.saturating_add(coin), Amount::MAX_MONEY);
+        assert_eq!(coin.saturating_sub(coin * 2), Amount::ZERO);
+        assert_eq!(
+            vec![coin, coin, Amount::CENT].into_iter().sum::<Amount>(),
+            Amount::from_sat(200_100_000)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_checks_range() {
+        let max = Amount::MAX_MONEY.to_sat();
+        let amount: Amount = serde_json::from_str(&max.to_string()).unwrap();
+        assert_eq!(amount, Amount::MAX_MONEY);
+        assert_eq!(serde_json::to_string(&amount).unwrap(), max.to_string());
+        assert!(serde_json::from_str::<Amount>(&(max + 1).to_string()).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_sub_below_zero_panics() {
+        let _ = Amount::CENT - Amount::COIN;
+    }
+
+    #[test]
+    fn test_try_from_sat_and_fraction() {
+        assert_eq!(Amount::try_from_sat(1).unwrap(), Amount::from_sat(1));
+        assert!(Amount::try_from_sat(Amount::MAX_MONEY.to_sat() + 1).is_err());
+        assert_eq!(Amount::COIN.fraction(0.25), Amount::from_sat(25_000_000));
+        assert_eq!(Amount::COIN.fraction(1.5), Amount::COIN);
+        assert_eq!(Amount::COIN.fraction(-1.0), Amount::ZERO);
+        assert_eq!(Amount::MAX_MONEY.fraction(1.0), Amount::MAX_MONEY);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn test_from_sat_out_of_range_panics() {
+        let _ = Amount::from_sat(Amount::MAX_MONEY.to_sat() + 1);
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+use crate::amount::Amount;
+use crate::denom::{self, DENOM_THRESHOLD_MAX, DenomStrategy, DenomValue};
+use crate::error::SimError;
+use crate::network::Network;
+use crate::wallet::{Consolidate, Wallet};
+use rand::Rng;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Serialize)]
+pub struct AttackRow {
+    /// Attacker coins as a multiple of the coins already staking.
+    pub size: f64,
+    pub balance: Amount,
+    pub strategy: DenomStrategy,
+    pub stake_share: f64,
+    pub weight_share: f64,
+    /// Chance of producing `run_length` blocks in a row within the window.
+    pub run_probability: f64,
+    /// Mean blocks until the first such run, over the trials that got one.
+    pub mean_blocks_to_run: Option<f64>,
+    /// Chance of producing more than half the blocks in the window.
+    pub majority_probability: f64,
+}
+
+/// How likely an attacker who buys in at the network's current height is to
+/// take over block production. The attacker's coins are new stake on top of
+/// the existing weight, denominated the way that gives the most weight within
+/// the hashing threshold, and every block it wins locks the staked input just
+/// as it would for anyone else.
+#[derive(Debug, Serialize)]
+pub struct AttackTable {
+    pub block_height: u64,
+    pub honest_weight: u64,
+    pub run_length: u64,
+    pub window: u64,
+    pub trials: u64,
+    pub rows: Vec<AttackRow>,
+}
+
+impl AttackTable {
+    pub fn run<R: Rng + ?Sized>(
+        network: &Network,
+        sizes: &[f64],
+        run_length: u64,
+        window: u64,
+        trials: u64,
+        rng: &mut R,
+    ) -> Result<Self, SimError> {
+        let honest_weight = network.total_weight();
+        let staked = network.total_supply() - network.float();
+        let rows = sizes
+            .iter()
+            .map(|size| {
+                let balance = Amount::try_from_sat((staked.to_sat() as f64 * size) as u64)?;
+                let wallet = attacker_wallet(network, balance)?;
+                let weight = wallet.weight();
+
+                let mut runs = 0;
+                let mut run_blocks = 0;
+                let mut majorities = 0;
+                for _ in 0..trials {
+                    let trial = simulate(
+                        network,
+                        wallet.clone(),
+                        honest_weight,
+                        run_length,
+                        window,
+                        rng,
+                    )?;
+                    if let Some(blocks) = trial.first_run {
+                        runs += 1;
+                        run_blocks += blocks;
+                    }
+                    if trial.wins * 2 > window {
+                        majorities += 1;
+                    }
+                }
+
+                Ok(AttackRow {
+                    size: *size,
+                    balance,
+                    strategy: wallet.strategy(),
+                    stake_share: share(balance.to_sat(), staked.to_sat()),
+                    weight_share: share(weight, honest_weight),
+                    run_probability: ratio(runs, trials),
+                    mean_blocks_to_run: if runs > 0 {
+                        Some(run_blocks as f64 / runs as f64)
+                    } else {
+                        None
+                    },
+                    majority_probability: ratio(majorities, trials),
+                })
+            })
+            .collect::<Result<_, SimError>>()?;
+
+        Ok(Self {
+            block_height: network.block_height(),
+            honest_weight,
+            run_length,
+            window,
+            trials,
+            rows,
+        })
+    }
+}
+
+struct Trial {
+    wins: u64,
+    first_run: Option<u64>,
+}
+
+fn simulate<R: Rng + ?Sized>(
+    network: &Network,
+    mut wallet: Wallet,
+    honest_weight: u64,
+    run_length: u64,
+    window: u64,
+    rng: &mut R,
+) -> Result<Trial, SimError> {
+    let mut trial = Trial {
+        wins: 0,
+        first_run: None,
+    };
+    let mut run = 0;
+    for i in 0..window {
+        let block_height = network.block_height() + i;
+        // Rewards are staked as they mature and only merged into denoms once
+        // a locked input comes back, which keeps the trials cheap.
+        if wallet.is_maturing() {
+            wallet.mature_outputs(block_height, Consolidate::OnUnlock)?;
+        }
+        wallet.reweigh(block_height);
+
+        let weight = wallet.weight();
+        let p = share(weight, honest_weight);
+        if weight > 0 && rng.gen::<f64>() < p {
+            let maturity = network.maturity().at(block_height);
+            wallet.add_reward(
+                network.reward_schedule().reward(block_height),
+                block_height,
+                &maturity,
+            )?;
+            wallet.lock_denom(block_height, &maturity, rng)?;
+            trial.wins += 1;
+            run += 1;
+            if run == run_length && trial.first_run.is_none() {
+                trial.first_run = Some(i + 1);
+            }
+        } else {
+            run = 0;
+        }
+    }
+    Ok(trial)
+}
+
+/// The strategy with the most weight whose output count stays within the
+/// highest denom threshold, so the attacker's blocks aren't orphaned. The
+/// wallet is set up like an honest staker's, under the network's weight rule,
+/// stake modifiers and Zerocoin model.
+fn attacker_wallet(network: &Network, balance: Amount) -> Result<Wallet, SimError> {
+    let build = |strategy: u64| {
+        let mut wallet = Wallet::try_init(balance, strategy, DENOM_THRESHOLD_MAX)?;
+        wallet.set_weight_rule(network.weight_rule());
+        wallet.set_stake_mods(network.stake_mods().clone());
+        if network.zerocoin().is_some() {
+            wallet.set_zerocoin(network.zerocoin(), network.block_height())?;
+        }
+        Ok(wallet)
+    };
+    // What a strategy bracket is split into under the model in force.
+    let target = |bracket: u32| match network.zerocoin() {
+        Some(_) => DenomValue::ALL[bracket as usize].value().to_sat(),
+        None => denom::bracket_target(bracket),
+    };
+    let wallets = DenomStrategy::ALL
+        .iter()
+        .enumerate()
+        .filter(|(_, strategy)| {
+            // Skip strategies that can't fit the balance before splitting it.
+            strategy
+                .brackets()
+                .iter()
+                .max()
+                .is_none_or(|b| balance.to_sat() / target(*b) <= DENOM_THRESHOLD_MAX)
+        })
+        .map(|(strategy, _)| build(strategy as u64))
+        .collect::<Result<Vec<_>, SimError>>()?;
+    match wallets
+        .into_iter()
+        .filter(|p| p.count() <= DENOM_THRESHOLD_MAX)
+        .max_by_key(Wallet::weight)
+    {
+        Some(wallet) => Ok(wallet),
+        None => build(DenomStrategy::ALL.len() as u64 - 1),
+    }
+}
+
+/// The attacker's share when `value` is added on top of `others`.
+fn share(value: u64, others: u64) -> f64 {
+    if value == 0 {
+        0.0
+    } else {
+        value as f64 / (value + others) as f64
+    }
+}
+
+fn ratio(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+impl fmt::Display for AttackTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Attack at block {}: {} blocks in a row or a majority of {} blocks, {} trials.",
+            self.block_height, self.run_length, self.window, self.trials
+        )?;
+        writeln!(
+            f,
+            "{:>6} {:>20} {:>8} {:>9} {:>8} {:>10} {:>10}",
+            "size", "strategy", "stake%", "weight%", "run%", "blocks", "majority%"
+        )?;
+        for row in &self.rows {
+            let blocks = match row.mean_blocks_to_run {
+                Some(blocks) => format!("{:.0}", blocks),
+                None => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "{:>6.2} {:>20} {:>7.2}% {:>8.2}% {:>7.2}% {:>10} {:>9.2}%",
+                row.size,
+                format!("{:?}", row.strategy),
+                row.stake_share * 100.0,
+                row.weight_share * 100.0,
+                row.run_probability * 100.0,
+                blocks,
+                row.majority_probability * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zerocoin::ZerocoinModel;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn test_bigger_attackers_do_better() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut network = Network::new();
+        network.create_stakers(&mut rng).unwrap();
+        let table = AttackTable::run(&network, &[0.1, 1.0, 3.0], 6, 200, 100, &mut rng).unwrap();
+
+        let rows = &table.rows;
+        assert!(rows[0].weight_share < rows[1].weight_share);
+        assert!(rows[0].run_probability <= rows[2].run_probability);
+        assert!(rows[0].majority_probability < 0.01);
+        assert!(rows[2].majority_probability > 0.9);
+        assert!(rows[2].mean_blocks_to_run.is_some());
+    }
+
+    #[test]
+    fn test_attacker_stakes_zerocoins_under_zerocoin() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut network = Network::new();
+        network.create_stakers(&mut rng).unwrap();
+        network
+            .set_zerocoin(Some(ZerocoinModel::default()))
+            .unwrap();
+        let wallet = attacker_wallet(&network, Amount::from_coins(1_000_000)).unwrap();
+        assert_eq!(wallet.zerocoin(), network.zerocoin());
+        assert!(wallet.weight() > 0);
+        assert!(wallet.mature().iter().all(|p| p.is_zerocoin()));
+    }
+
+    #[test]
+    fn test_attacker_fits_zerocoin_denominations() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut network = Network::new();
+        network.create_stakers(&mut rng).unwrap();
+        network
+            .set_zerocoin(Some(ZerocoinModel::default()))
+            .unwrap();
+        // Too much for the largest bracket target, not for the largest
+        // Zerocoin denomination.
+        let balance = Amount::from_coins(100_000_000);
+        let wallet = attacker_wallet(&network, balance).unwrap();
+        assert!(wallet.count() <= DENOM_THRESHOLD_MAX);
+        assert!(
+            wallet
+                .mature()
+                .iter()
+                .all(|p| p.value() == DenomValue::D10000.value())
+        );
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::parse::number;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// On/off Markov chain for whether a staker's node is online. The per block
+/// chance of going offline shrinks, and of coming back grows, with computer
+/// strength: `rate * exp(∓strength_scale * computer_strength)`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct AvailabilityModel {
+    pub failure_rate: f64,
+    pub recovery_rate: f64,
+    pub strength_scale: f64,
+}
+
+impl Default for AvailabilityModel {
+    /// An average home node drops out about every two days and takes about
+    /// four hours to come back.
+    fn default() -> Self {
+        Self {
+            failure_rate: 1.0 / 2_880.0,
+            recovery_rate: 1.0 / 240.0,
+            strength_scale: 1.0,
+        }
+    }
+}
+
+impl AvailabilityModel {
+    pub fn failure_rate(&self, computer_strength: f64) -> f64 {
+        (self.failure_rate * (-self.strength_scale * computer_strength).exp()).min(1.0)
+    }
+
+    pub fn recovery_rate(&self, computer_strength: f64) -> f64 {
+        (self.recovery_rate * (self.strength_scale * computer_strength).exp()).min(1.0)
+    }
+
+    /// Long run share of blocks a node of this strength is online.
+    pub fn steady_state(&self, computer_strength: f64) -> f64 {
+        let failure = self.failure_rate(computer_strength);
+        let recovery = self.recovery_rate(computer_strength);
+        if failure + recovery == 0.0 {
+            return 1.0;
+        }
+        recovery / (failure + recovery)
+    }
+}
+
+/// Parses `default` or `FAILURE:RECOVERY:STRENGTH_SCALE`.
+impl FromStr for AvailabilityModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "default" {
+            return Ok(Self::default());
+        }
+
+        let parts: Vec<f64> = s.split(':').map(number).collect::<Result<_, _>>()?;
+        match parts.as_slice() {
+            [failure_rate, recovery_rate, strength_scale]
+                if (0.0..=1.0).contains(failure_rate) && (0.0..=1.0).contains(recovery_rate) =>
+            {
+                Ok(Self {
+                    failure_rate: *failure_rate,
+                    recovery_rate: *recovery_rate,
+                    strength_scale: *strength_scale,
+                })
+            }
+            _ => Err(format!(
+                "Expected default or FAILURE:RECOVERY:STRENGTH_SCALE with rates in [0, 1], got {}.",
+                s
+            )),
+        }
+    }
+}
+
+/// A staker's position in the availability chain. Rather than flipping a
+/// coin every block, the length of each online or offline spell is drawn
+/// once when the spell starts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Uptime {
+    offline: bool,
+    next_toggle: Option<u64>,
+    tracked_from: u64,
+    since: u64,
+    online_blocks: u64,
+}
+
+impl Uptime {
+    pub fn is_online(&self) -> bool {
+        !self.offline
+    }
+
+    /// Advances the chain to `block_height`.
+    pub fn update<R: Rng + ?Sized>(
+        &mut self,
+        model: &AvailabilityModel,
+        computer_strength: f64,
+        block_height: u64,
+        rng: &mut R,
+    ) {
+        match self.next_toggle {
+            None => {
+                self.tracked_from = block_height;
+                self.since = block_height;
+                let failure = model.failure_rate(computer_strength);
+                self.next_toggle = Some(block_height + spell(failure, rng));
+            }
+            Some(toggle) if block_height >= toggle => {
+                if !self.offline {
+                    self.online_blocks += block_height - self.since;
+                }
+                self.offline = !self.offline;
+                self.since = block_height;
+                let rate = if self.offline {
+                    model.recovery_rate(computer_strength)
+                } else {
+                    model.failure_rate(computer_strength)
+                };
+                self.next_toggle = Some(block_height + spell(rate, rng));
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// Share of blocks spent online since tracking started. Stakers that were
+    /// never tracked count as always online.
+    pub fn availability(&self, block_height: u64) -> f64 {
+        let tracked = block_height.saturating_sub(self.tracked_from);
+        if self.next_toggle.is_none() || tracked == 0 {
+            return 1.0;
+        }
+        let mut online = self.online_blocks;
+        if !self.offline {
+            online += block_height.saturating_sub(self.since);
+        }
+        online as f64 / tracked as f64
+    }
+}
+
+/// Length of a spell that ends with probability `rate` each block.
+fn spell<R: Rng + ?Sized>(rate: f64, rng: &mut R) -> u64 {
+    if rate <= 0.0 {
+        return u64::MAX / 2;
+    }
+    if rate >= 1.0 {
+        return 1;
+    }
+    let uniform: f64 = rng.gen_range(f64::EPSILON, 1.0);
+    (uniform.ln() / (1.0 - rate).ln()).ceil().max(1.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn test_stronger_computers_stay_online() {
+        let model = AvailabilityModel::default();
+        assert!(model.steady_state(2.0) > model.steady_state(0.0));
+        assert!(model.steady_state(0.0) > model.steady_state(-2.0));
+    }
+
+    #[test]
+    fn test_availability_matches_steady_state() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let model = AvailabilityModel {
+            failure_rate: 0.01,
+            recovery_rate: 0.04,
+            strength_scale: 0.0,
+        };
+        let mut uptime = Uptime::default();
+        for height in 0..200_000 {
+            uptime.update(&model, 0.0, height, &mut rng);
+        }
+        assert!((uptime.availability(200_000) - 0.8).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_parse_model() {
+        assert_eq!(
+            "default".parse::<AvailabilityModel>().unwrap(),
+            AvailabilityModel::default()
+        );
+        assert!("0.1:0.2:1".parse::<AvailabilityModel>().is_ok());
+        assert!("2:0.2:1".parse::<AvailabilityModel>().is_err());
+    }
+}
@@ -23,389 +23,438 @@
 // POSSIBILITY OF SUCH DAMAGE.
 
 use rand::prelude::*;
-use rand_distr::{Distribution, LogNormal, Normal};
-use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::io::Write;
-use std::ops::Range;
-use std::time::SystemTime;
-
-static STAKE_REWARD: u64 = 50;
-// static MAX_SUPPLY: u64 = 300_000_000;
-static SUPER_BLOCK: u64 = 43_200;
-static REWARD_REDUCTION_BLOCK: u64 = 525_960;
-
-// TODO: Change numbers to these.
-
-
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Staker {
-    id: u64,
-    start_balance: u64,
-    start_pct_total: f64,
-    balance_spendable: u64,
-    balance_immature: u64,
-    percent_total: f64,
-    change_pct: f64,
-    computer_strength: f64,
-    // TODO transaction count? Happens everytime denoms move.
-    orphaned_count: u64,
-    #[serde(skip_serializing)]
-    denoms: Denoms,
-    #[serde(skip_serializing)]
-    range: Range<f64>,
+use std::process;
+use std::str::FromStr;
+use veil_pos_simulation::adversary::{AdversaryReport, AdversarySpec};
+use veil_pos_simulation::attack::AttackTable;
+use veil_pos_simulation::availability::AvailabilityModel;
+use veil_pos_simulation::capacity::CapacityModel;
+use veil_pos_simulation::checkpoint::Checkpoint;
+use veil_pos_simulation::consensus::{Activation, ConsensusSchedule};
+use veil_pos_simulation::denom;
+use veil_pos_simulation::exchange::{Exchange, ExchangeReport};
+use veil_pos_simulation::fee::FeeModel;
+use veil_pos_simulation::fork::{Modification, run_fork};
+use veil_pos_simulation::invariant::InvariantChecker;
+use veil_pos_simulation::maturity::{MaturityPhase, MaturitySchedule};
+use veil_pos_simulation::migration::MigrationModel;
+use veil_pos_simulation::network::{Network, REWARD_REDUCTION_BLOCK, SimRng};
+use veil_pos_simulation::parse;
+use veil_pos_simulation::pool::{Pool, PoolReport};
+use veil_pos_simulation::population::{PopulationPhase, PopulationSchedule};
+use veil_pos_simulation::propagation::{PropagationModel, Sweep};
+use veil_pos_simulation::report::Report;
+use veil_pos_simulation::transaction::TransactionModel;
+use veil_pos_simulation::weight::{StakeModRule, StakeMods, WeightRule};
+use veil_pos_simulation::zerocoin::ZerocoinModel;
+
+struct Options {
+    seed: Option<u64>,
+    resume: Option<String>,
+    checkpoint: String,
+    checkpoint_interval: u64,
+    check_invariants: u64,
+    fork: Option<String>,
+    modify: Option<Modification>,
+    fork_blocks: u64,
+    population: Vec<PopulationPhase>,
+    non_staking: f64,
+    uptime: Option<AvailabilityModel>,
+    capacity: Option<CapacityModel>,
+    propagation: Option<PropagationModel>,
+    propagation_sweep: Option<String>,
+    latencies: Vec<f64>,
+    block_times: Vec<f64>,
+    sweep_blocks: u64,
+    adversaries: Vec<AdversarySpec>,
+    pools: Vec<Pool>,
+    exchanges: Vec<Exchange>,
+    transactions: Option<TransactionModel>,
+    fees: Option<FeeModel>,
+    maturity: Vec<MaturityPhase>,
+    weight: WeightRule,
+    stake_mods: Vec<StakeModRule>,
+    zerocoin: Option<ZerocoinModel>,
+    activations: Vec<Activation>,
+    migration: Option<MigrationModel>,
+    brackets: bool,
+    attack: Option<String>,
+    attack_sizes: Vec<f64>,
+    attack_run: u64,
+    attack_window: u64,
+    attack_trials: u64,
 }
 
-impl Staker {
-    fn new(balance: u64, id: u64, start_pct_total: f64, rng: &mut ThreadRng) -> Self {
-        let normal = Normal::new(0.0, 1.0).unwrap();
-        let denom_strat = rng.gen_range(0, 7);
-        let computer_strength = normal.sample(rng);
-
-        let x: f64 = computer_strength;
-        let in_min: f64 = -5.0;
-        let in_max: f64 = 5.0;
-        let out_min: f64 = DENOM_THRESHOLD_MIN as f64;
-        let out_max: f64 = DENOM_THRESHOLD_MAX as f64;
-        let result: f64 = (x - in_min) * (out_max - out_min) / (in_max - in_min) + out_min;
-
-        Self {
-            id,
-            denoms: Denoms::new(balance, denom_strat.to_owned(), result as u64),
-            computer_strength,
-            start_balance: balance.to_owned(),
-            start_pct_total,
-            balance_spendable: balance,
-            balance_immature: 0,
-            percent_total: 0.0,
-            orphaned_count: 0,
-            range: Range {
-                start: 0.0,
-                end: 0.0,
-            },
-            change_pct: 0.0,
-        }
-    }
-
-    fn hit_stake(&mut self, block_height: u64, mut rng: &mut ThreadRng) {
-        if self.denoms.count() > self.denoms.denom_threshold {
-            let res = rng.gen_range(0, self.denoms.count());
-            if res > self.denoms.denom_threshold {
-                self.orphaned_count += 1;
-                return;
-            }
-        }
-
-        let reward = if block_height < REWARD_REDUCTION_BLOCK {
-            STAKE_REWARD
-        } else if block_height < REWARD_REDUCTION_BLOCK * 2 {
-            STAKE_REWARD - 10
-        } else if block_height < REWARD_REDUCTION_BLOCK * 3 {
-            STAKE_REWARD - 20
-        } else if block_height < REWARD_REDUCTION_BLOCK * 4 {
-            STAKE_REWARD - 30
-        } else {
-            STAKE_REWARD - 40
-        };
-
-        self.denoms.immature.push(ImmatureBalance {
-            is_stake: true,
-            reward,
-            height: block_height,
-            mature_height: block_height + 30,
-        });
-        self.denoms.immature_stake_count += 1;
-        self.balance_spendable += reward;
-        self.denoms.total_stake_count += 1;
-
-        self.lock_denom(block_height, &mut rng);
-    }
-
-    fn lock_denom(&mut self, block_height: u64, rng: &mut ThreadRng) {
-        use Denom::*;
-
-        #[derive(Debug)]
-        struct DenomRange {
-            denom: Denom,
-            range: Range<f64>,
+impl Options {
+    fn parse() -> Self {
+        let mut options = Options {
+            seed: None,
+            resume: None,
+            checkpoint: "checkpoint.json".to_owned(),
+            checkpoint_interval: 100_000,
+            check_invariants: 0,
+            fork: None,
+            modify: None,
+            fork_blocks: 100_000,
+            population: Vec::new(),
+            non_staking: 0.0,
+            uptime: None,
+            capacity: None,
+            propagation: None,
+            propagation_sweep: None,
+            latencies: vec![0.5, 2.0, 8.0],
+            block_times: vec![30.0, 60.0, 120.0],
+            sweep_blocks: 100_000,
+            adversaries: Vec::new(),
+            pools: Vec::new(),
+            exchanges: Vec::new(),
+            transactions: None,
+            fees: None,
+            maturity: Vec::new(),
+            weight: WeightRule::default(),
+            stake_mods: Vec::new(),
+            zerocoin: None,
+            activations: Vec::new(),
+            migration: None,
+            brackets: false,
+            attack: None,
+            attack_sizes: vec![0.1, 0.25, 0.5, 1.0, 2.0],
+            attack_run: 6,
+            attack_window: 1_000,
+            attack_trials: 1_000,
         };
 
-        let mut denom_ranges: Vec<DenomRange> = Vec::new();
-        if self.denoms.d10 > 0 {
-            denom_ranges.push(DenomRange {
-                denom: D10,
-                range: Range {
-                    start: 0.0,
-                    end: 0.0,
-                },
-            })
-        }
-        if self.denoms.d100 > 0 {
-            denom_ranges.push(DenomRange {
-                denom: D100,
-                range: Range {
-                    start: 0.0,
-                    end: 0.0,
-                },
-            })
-        }
-        if self.denoms.d1_000 > 0 {
-            denom_ranges.push(DenomRange {
-                denom: D1000,
-                range: Range {
-                    start: 0.0,
-                    end: 0.0,
-                },
-            })
-        }
-        if self.denoms.d10_000 > 0 {
-            denom_ranges.push(DenomRange {
-                denom: D10000,
-                range: Range {
-                    start: 0.0,
-                    end: 0.0,
-                },
-            })
-        }
-
-        let mut start = 0.0;
-        for mut denom_range in &mut denom_ranges {
-            let pct = self.denoms.probability(&denom_range.denom);
-            let range = start..start + pct;
-            denom_range.range = range;
-            start += pct;
-        }
-
-        let winning_pct = rng.gen_range(0.0, start);
-        let winner = denom_ranges
-            .iter_mut()
-            .find(|p| p.range.contains(&winning_pct));
-
-        if let Some(winner) = winner {
-            // TODO: Make ImmatureBalance once, update after.
-            match winner.denom {
-                D10 => {
-                    self.denoms.d10 -= 1;
-                    self.denoms.immature.push(ImmatureBalance {
-                        is_stake: false,
-                        reward: 10,
-                        height: block_height,
-                        mature_height: block_height + 1000,
-                    });
-                }
-                D100 => {
-                    self.denoms.d100 -= 1;
-                    self.denoms.immature.push(ImmatureBalance {
-                        is_stake: false,
-                        reward: 100,
-                        height: block_height,
-                        mature_height: block_height + 1000,
-                    });
-                }
-                D1000 => {
-                    self.denoms.d1_000 -= 1;
-                    self.denoms.immature.push(ImmatureBalance {
-                        is_stake: false,
-                        reward: 1_000,
-                        height: block_height,
-                        mature_height: block_height + 1000,
-                    });
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next().unwrap_or_else(|| {
+                    eprintln!("Missing value for {}.", arg);
+                    process::exit(1);
+                })
+            };
+            match arg.as_str() {
+                "--seed" => options.seed = Some(parse_number(&arg, &value())),
+                "--resume" => options.resume = Some(value()),
+                "--checkpoint" => options.checkpoint = value(),
+                "--checkpoint-interval" => {
+                    options.checkpoint_interval = parse_number(&arg, &value())
                 }
-                D10000 => {
-                    self.denoms.d10_000 -= 1;
-                    self.denoms.immature.push(ImmatureBalance {
-                        is_stake: false,
-                        reward: 10_000,
-                        height: block_height,
-                        mature_height: block_height + 1000,
-                    });
+                "--check-invariants" => options.check_invariants = parse_number(&arg, &value()),
+                "--fork" => options.fork = Some(value()),
+                "--modify" => options.modify = Some(parse_or_exit(&arg, &value())),
+                "--fork-blocks" => options.fork_blocks = parse_number(&arg, &value()),
+                "--non-staking" => options.non_staking = parse_or_exit(&arg, &value()),
+                "--uptime" => options.uptime = Some(parse_or_exit(&arg, &value())),
+                "--capacity" => options.capacity = Some(parse_or_exit(&arg, &value())),
+                "--propagation" => options.propagation = Some(parse_or_exit(&arg, &value())),
+                "--propagation-sweep" => options.propagation_sweep = Some(value()),
+                "--latencies" => options.latencies = parse_list(&arg, &value()),
+                "--block-times" => options.block_times = parse_list(&arg, &value()),
+                "--sweep-blocks" => options.sweep_blocks = parse_number(&arg, &value()),
+                "--population" => options.population.push(parse_or_exit(&arg, &value())),
+                "--adversary" => options.adversaries.push(parse_or_exit(&arg, &value())),
+                "--pool" => options.pools.push(parse_or_exit(&arg, &value())),
+                "--exchange" => options.exchanges.push(parse_or_exit(&arg, &value())),
+                "--transactions" => options.transactions = Some(parse_or_exit(&arg, &value())),
+                "--fees" => options.fees = Some(parse_or_exit(&arg, &value())),
+                "--maturity" => options.maturity.push(parse_or_exit(&arg, &value())),
+                "--weight" => options.weight = parse_or_exit(&arg, &value()),
+                "--stake-mod" => options.stake_mods.push(parse_or_exit(&arg, &value())),
+                "--zerocoin" => options.zerocoin = Some(parse_or_exit(&arg, &value())),
+                "--activate" => options.activations.push(parse_or_exit(&arg, &value())),
+                "--migration" => options.migration = Some(parse_or_exit(&arg, &value())),
+                "--brackets" => options.brackets = true,
+                "--attack" => options.attack = Some(value()),
+                "--attack-sizes" => options.attack_sizes = parse_list(&arg, &value()),
+                "--attack-run" => options.attack_run = parse_number(&arg, &value()),
+                "--attack-window" => options.attack_window = parse_number(&arg, &value()),
+                "--attack-trials" => options.attack_trials = parse_number(&arg, &value()),
+                _ => {
+                    eprintln!("Unknown option {}.", arg);
+                    eprintln!(
+                        "Usage: main [--seed N] [--resume FILE] [--checkpoint FILE] \
+                         [--checkpoint-interval BLOCKS] [--check-invariants BLOCKS] \
+                         [--non-staking FRACTION] \
+                         [--uptime default|FAILURE:RECOVERY:SCALE] \
+                         [--capacity default|HASH_RATE:SCALE:INTERVAL] \
+                         [--propagation LATENCY:BLOCK_TIME] \
+                         [--population SPEC]... [--adversary SPEC]... \
+                         [--pool STRENGTH:THRESHOLD:FEE:MAX_COINS]... \
+                         [--exchange SHARE:stake|hold:STRATEGY:DEPOSITS:WITHDRAWALS]... \
+                         [--transactions RATE:MAX_FRACTION:EXTERNAL] \
+                         [--fees default|INPUT_BYTES:OUTPUT_BYTES:MEDIAN_RATE:SIGMA] \
+                         [--maturity HEIGHT:STAKE:INPUT_LOCK:TRANSFER]... \
+                         [--weight RULE] [--stake-mod RULE]... \
+                         [--zerocoin default|MINT_FEE:CHECKPOINT_INTERVAL:CONFIRMATIONS] \
+                         [--activate HEIGHT:CHANGE]... \
+                         [--migration DELAY:NEVER:COST] [--brackets] \
+                         [--fork FILE --modify SPEC [--fork-blocks BLOCKS]] \
+                         [--propagation-sweep FILE [--latencies L,..] \
+                         [--block-times T,..] [--sweep-blocks BLOCKS]] \
+                         [--attack FILE [--attack-sizes S,..] [--attack-run K] \
+                         [--attack-window BLOCKS] [--attack-trials N]]"
+                    );
+                    process::exit(1);
                 }
             }
-        } else {
-            println!("Impossibruuu!");
         }
-    }
 
-    fn update(&mut self, total_supply: u64) {
-        self.percent_total = self.balance_spendable as f64 / total_supply as f64;
-        self.change_pct = self.percent_total / self.start_pct_total;
-    }
-
-    fn are_stakes_maturing(&mut self) -> bool {
-        !self.denoms.immature.is_empty()
+        options
     }
+}
 
-    fn mature_balances(&mut self, block_height: u64) {
-        let mature = self
-            .denoms
-            .immature
-            .iter_mut()
-            .enumerate()
-            .find(|p| p.1.mature_height <= block_height);
-        if let Some((pos, mature_balance)) = mature {
-            if mature_balance.is_stake {
-                self.denoms.immature_stake_count -= 1;
-                self.denoms.conf_stake_count += 1;
-            }
+/// Parses the value of `option`, or prints why it is invalid and exits.
+fn parse_or_exit<T: FromStr>(option: &str, value: &str) -> T
+where
+    T::Err: fmt::Display,
+{
+    value.parse().unwrap_or_else(|e| {
+        eprintln!("{}: {}", option, e);
+        process::exit(1);
+    })
+}
 
-            let mut balance_left = mature_balance.reward;
-            while balance_left > 0 {
-                if balance_left >= 10_000 {
-                    self.denoms.d10_000 += 1;
-                    balance_left -= 10_000;
-                }
-                if balance_left >= 1_000 {
-                    self.denoms.d1_000 += 1;
-                    balance_left -= 1_000;
-                }
-                if balance_left >= 100 {
-                    self.denoms.d100 += 1;
-                    balance_left -= 100;
-                }
-                if balance_left >= 10 {
-                    self.denoms.d10 += 1;
-                    balance_left -= 10;
-                }
-            }
+/// Parses the number given to `option` like the numbers of a spec, or
+/// prints why it is invalid and exits.
+fn parse_number<T: FromStr>(option: &str, value: &str) -> T
+where
+    T::Err: fmt::Display,
+{
+    parse::number(value).unwrap_or_else(|e| {
+        eprintln!("{}: {}", option, e);
+        process::exit(1);
+    })
+}
 
-            self.denoms.immature.remove(pos);
-            self.denoms.update_denoms(block_height);
-        }
-    }
+/// Parses the comma separated numbers given to `option`.
+fn parse_list(option: &str, value: &str) -> Vec<f64> {
+    value.split(',').map(|p| parse_number(option, p)).collect()
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Network {
-    stakers: Vec<Staker>,
-    total_supply: u64,
-    block_height: u64,
+fn propagation_sweep(path: &str, latencies: &[f64], block_times: &[f64], blocks: u64) {
+    println!("Loading checkpoint {}.", path);
+    let checkpoint = Checkpoint::load(path).unwrap_or_else(|e| {
+        eprintln!("Could not load checkpoint {}: {}", path, e);
+        process::exit(1);
+    });
+
+    println!(
+        "Running {} blocks from block {} for every latency and block time.",
+        blocks,
+        checkpoint.network.block_height()
+    );
+    let sweep = Sweep::run(
+        &checkpoint.network,
+        &checkpoint.rng,
+        latencies,
+        block_times,
+        blocks,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    println!("\n{}", sweep);
+
+    let json = serde_json::to_string(&sweep).unwrap();
+    let file_name = "sweep.json";
+    fs::write(file_name, json).unwrap();
+    println!("Sweep written to file {} in the base directory.", file_name);
 }
 
-impl Network {
-    fn new() -> Self {
-        Self {
-            stakers: Vec::new(),
-            total_supply: SUPER_BLOCK * STAKE_REWARD * 6, // Start 6 months, gets more stakers.
-            block_height: SUPER_BLOCK * 6,
-        }
-    }
+fn attack(path: &str, options: &Options) {
+    println!("Loading checkpoint {}.", path);
+    let checkpoint = Checkpoint::load(path).unwrap_or_else(|e| {
+        eprintln!("Could not load checkpoint {}: {}", path, e);
+        process::exit(1);
+    });
+
+    let mut rng = checkpoint.rng.clone();
+    let table = AttackTable::run(
+        &checkpoint.network,
+        &options.attack_sizes,
+        options.attack_run,
+        options.attack_window,
+        options.attack_trials,
+        &mut rng,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    println!("\n{}", table);
+
+    let json = serde_json::to_string(&table).unwrap();
+    let file_name = "attack.json";
+    fs::write(file_name, json).unwrap();
+    println!(
+        "Attack table written to file {} in the base directory.",
+        file_name
+    );
+}
 
-    fn create_stakers(&mut self, rng: &mut ThreadRng) {
-        let mut total_staking_supply = self.total_supply as isize;
-        let mut id = 0;
-        let log_normal = LogNormal::new(0.1, 1.5).unwrap();
-        loop {
-            let mut balance = (log_normal.sample(&mut rand::thread_rng()) * 5_000f64) as u64;
-
-            let left_staking_supply = total_staking_supply - balance as isize;
-            if left_staking_supply < 0 {
-                balance = total_staking_supply as u64;
-                total_staking_supply = 0;
-            } else {
-                total_staking_supply -= balance as isize;
-            }
+fn fork(path: &str, modification: Modification, blocks: u64) {
+    println!("Loading checkpoint {}.", path);
+    let checkpoint = Checkpoint::load(path).unwrap_or_else(|e| {
+        eprintln!("Could not load checkpoint {}: {}", path, e);
+        process::exit(1);
+    });
 
-            self.stakers.push(Staker::new(
-                balance,
-                id,
-                balance as f64 / self.total_supply as f64,
-                rng,
-            ));
+    println!(
+        "Running both branches from block {} for {} blocks.",
+        checkpoint.network.block_height(),
+        blocks
+    );
+    let comparison = run_fork(&checkpoint, modification, blocks).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    println!("\n{}", comparison);
+
+    let json = serde_json::to_string(&comparison).unwrap();
+    let file_name = "fork.json";
+    fs::write(file_name, json).unwrap();
+    println!(
+        "Comparison written to file {} in the base directory.",
+        file_name
+    );
+}
 
-            if total_staking_supply == 0 {
-                break;
-            }
+fn main() {
+    println!("Starting...");
+    let options = Options::parse();
 
-            id += 1;
-        }
+    if options.brackets {
+        let bracket_mod = match options.weight {
+            WeightRule::Bracket(rule) => rule.bracket_mod,
+            WeightRule::CoinAge(rule) => rule.base.bracket_mod,
+            _ => denom::DENOM_BRACKET_MOD,
+        };
+        print!("{}", denom::bracket_table(bracket_mod));
+        return;
     }
 
-    fn update_stakers(&mut self) {
-        let total_supply = self.total_supply;
-        self.stakers.iter_mut().for_each(|p| p.update(total_supply));
+    if let Some(path) = &options.fork {
+        let modification = options.modify.clone().unwrap_or_else(|| {
+            eprintln!("--fork needs a --modify.");
+            process::exit(1);
+        });
+        fork(path, modification, options.fork_blocks);
+        return;
     }
 
-    fn update_total_supply(&mut self) {
-        if self.block_height >= SUPER_BLOCK + 1000 {
-            if self.block_height < REWARD_REDUCTION_BLOCK {
-                self.total_supply += 50;
-            } else if self.block_height < REWARD_REDUCTION_BLOCK * 2 {
-                self.total_supply += 40;
-            } else if self.block_height < REWARD_REDUCTION_BLOCK * 3 {
-                self.total_supply += 30;
-            } else if self.block_height < REWARD_REDUCTION_BLOCK * 4 {
-                self.total_supply += 20;
-            } else {
-                self.total_supply += 10;
-            }
-        }
+    if let Some(path) = &options.propagation_sweep {
+        propagation_sweep(
+            path,
+            &options.latencies,
+            &options.block_times,
+            options.sweep_blocks,
+        );
+        return;
     }
 
-    fn stake(&mut self, mut rng: &mut ThreadRng) {
-        let mut start = 0.0;
-        for mut staker in &mut self.stakers {
-            if staker.are_stakes_maturing() {
-                staker.mature_balances(self.block_height);
-            }
+    if let Some(path) = &options.attack {
+        attack(path, &options);
+        return;
+    }
 
-            staker.range = Range {
-                start: 0.0,
-                end: 0.0,
-            };
-            let pct = staker.denoms.stake_probability(self.total_supply);
-            let range = start..start + pct;
-            staker.range = range;
-            start += pct;
+    let Checkpoint {
+        mut network,
+        mut rng,
+        start_block_height: starting_block_height,
+        end_block_height,
+    } = match &options.resume {
+        Some(path) => {
+            println!("Resuming from checkpoint {}.", path);
+            Checkpoint::load(path).unwrap_or_else(|e| {
+                eprintln!("Could not load checkpoint {}: {}", path, e);
+                process::exit(1);
+            })
         }
+        None => {
+            let mut rng = match options.seed {
+                Some(seed) => SimRng::seed_from_u64(seed),
+                None => SimRng::from_entropy(),
+            };
 
-        let winning_pct = rng.gen_range(0.0, start);
-        let winner = self
-            .stakers
-            .iter_mut()
-            .find(|p| p.range.contains(&winning_pct));
+            println!("Generating network.");
+            let mut network: Network = Network::new();
+            network.set_population(PopulationSchedule::new(options.population.clone()));
+            network.set_non_staking_fraction(options.non_staking);
+            network.set_availability(options.uptime);
+            network.set_capacity(options.capacity);
+            network.set_propagation(options.propagation);
+            network.set_exchanges(options.exchanges.clone());
+            network.set_transactions(options.transactions);
+            network.set_fees(options.fees);
+            network.set_maturity(MaturitySchedule::new(options.maturity.clone()));
+
+            println!("Generating stakers.");
+            let created = network.create_stakers(&mut rng).and_then(|_| {
+                network.set_weight_rule(options.weight);
+                network.set_stake_mods(StakeMods::new(options.stake_mods.clone()));
+                network.set_zerocoin(options.zerocoin)
+            });
+            if let Err(e) = created {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+            network.set_migration_model(options.migration);
+            network.set_consensus(ConsensusSchedule::new(options.activations.clone()));
+            for pool in &options.pools {
+                network.add_pool(pool.clone());
+            }
+            for adversary in &options.adversaries {
+                let id = adversary.apply(&mut network).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                });
+                println!("Adversary {:?} staking as {}.", adversary.behavior, id);
+            }
 
-        if let Some(winner) = winner {
-            winner.hit_stake(self.block_height, &mut rng);
-        } else {
-            println!("Impossibruuu!");
+            let starting_block_height = network.block_height();
+            Checkpoint::new(
+                network,
+                rng,
+                starting_block_height,
+                REWARD_REDUCTION_BLOCK * 10,
+            )
         }
-    }
-}
-
-fn main() {
-    println!("Starting...");
-    let mut rng: ThreadRng = rand::thread_rng();
-
-    println!("Generating network.");
-    let mut network: Network = Network::new();
+    };
 
-    println!("Generating stakers.");
-    network.create_stakers(&mut rng);
+    println!("{} stakers.", network.stakers().len());
 
-    println!("{} stakers generated.", network.stakers.len());
-
-    let end_block_height = REWARD_REDUCTION_BLOCK * 10;
-    let starting_block_height = network.block_height;
     println!(
         "Generating history from block {} to block {}.",
-        starting_block_height, end_block_height
+        network.block_height(),
+        end_block_height
     );
+    let mut checker = InvariantChecker::new(options.check_invariants);
     // TODO: Fix time
     // let mut now = SystemTime::now();
-    while network.block_height <= end_block_height {
-        network.stake(&mut rng);
-        network.block_height += 1;
-        network.update_total_supply();
+    while network.block_height() <= end_block_height {
+        if let Err(e) = network.step(&mut rng) {
+            eprintln!("\nStopped at block {}: {}", network.block_height(), e);
+            process::exit(1);
+        }
+        if let Err(e) = checker.step(&network) {
+            eprintln!("\n{}", e);
+            process::exit(1);
+        }
 
-        if network.block_height % 100 == 0 {
-            let pct_done = (network.block_height - starting_block_height) as f64
+        if network.block_height().is_multiple_of(100) {
+            let pct_done = (network.block_height() - starting_block_height) as f64
                 / end_block_height as f64
                 * 100.0;
             print!(
                 "\rAt block {} of {}.",
-                network.block_height, end_block_height
+                network.block_height(),
+                end_block_height
             );
 
             print!(" [");
@@ -438,19 +487,89 @@ fn main() {
             io::stdout().flush().unwrap();
         }
 
-        if network.block_height == end_block_height {
+        if options.checkpoint_interval > 0
+            && network
+                .block_height()
+                .is_multiple_of(options.checkpoint_interval)
+        {
+            let checkpoint = Checkpoint::new(network, rng, starting_block_height, end_block_height);
+            if let Err(e) = checkpoint.save(&options.checkpoint) {
+                eprintln!("\nCould not write checkpoint {}: {}", options.checkpoint, e);
+            }
+            network = checkpoint.network;
+            rng = checkpoint.rng;
+        }
+
+        if network.block_height() == end_block_height {
             print!(
                 "\rAt block {} of {}. [########################################] 100.00% done!",
-                network.block_height, end_block_height
+                network.block_height(),
+                end_block_height
             );
             io::stdout().flush().unwrap();
         }
     }
     network.update_stakers();
     println!("\nBlockchain history generated.");
+    println!(
+        "Staking participation: {:.2}% of supply.",
+        network.participation_rate() * 100.0
+    );
+    if network.propagation().is_some() || !options.adversaries.is_empty() {
+        println!(
+            "{} blocks orphaned by competing blocks.",
+            network.orphaned_blocks()
+        );
+    }
 
-    let json = serde_json::to_string(&network.stakers).unwrap();
+    let summaries: Vec<_> = network.stakers().iter().map(|p| p.summary()).collect();
+    let json = serde_json::to_string(&summaries).unwrap();
     let file_name = "data.json";
     fs::write(file_name, json).unwrap();
     println!("JSON written to file {} in the base directory.", file_name);
+
+    let report = Report::from_stakers(network.stakers());
+    println!("\n{}", report);
+    let json = serde_json::to_string(&report).unwrap();
+    let file_name = "report.json";
+    fs::write(file_name, json).unwrap();
+    println!(
+        "Report written to file {} in the base directory.",
+        file_name
+    );
+
+    if network.fee_report().eras.iter().any(|p| !p.fees.is_zero()) {
+        println!("\nBlock producer income per reward era:");
+        println!("{}", network.fee_report());
+    }
+
+    if network.zerocoin().is_some() {
+        println!("\nZerocoin minting costs per strategy:");
+        println!("{}", network.zerocoin_report());
+    }
+
+    if let Some(consensus) = network.consensus_report() {
+        println!("\nResults between consensus changes:");
+        println!("{}", consensus);
+    }
+
+    if let Some(migration) = network.migration_report() {
+        println!("\nMigration from Zerocoin to RingCT:");
+        println!("{}", migration);
+    }
+
+    if !network.exchanges().is_empty() {
+        let exchanges = ExchangeReport::new(network.exchanges(), network.stakers());
+        println!("\n{}", exchanges);
+    }
+
+    if !network.pools().is_empty() {
+        let pools = PoolReport::new(network.pools(), network.stakers());
+        println!("\n{}", pools);
+    }
+
+    let adversaries = AdversaryReport::from_stakers(network.stakers());
+    if !adversaries.is_empty() {
+        println!("\n{}", adversaries);
+    }
 }
@@ -0,0 +1,110 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::parse::number;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// How many outputs a staker's computer can hash per kernel search interval.
+/// A wallet hashes its heaviest outputs first, and a kernel found on an output
+/// it didn't get to in time is lost. This replaces the flat denom threshold
+/// when set.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct CapacityModel {
+    /// Kernel hashes per second for a computer of strength 0.
+    pub hash_rate: f64,
+    /// Hash rate scales with `exp(strength_scale * computer_strength)`.
+    pub strength_scale: f64,
+    /// Seconds between kernel searches.
+    pub search_interval: f64,
+}
+
+impl Default for CapacityModel {
+    fn default() -> Self {
+        Self {
+            hash_rate: 500.0,
+            strength_scale: 0.5,
+            search_interval: 16.0,
+        }
+    }
+}
+
+impl CapacityModel {
+    pub fn hashes_per_second(&self, computer_strength: f64) -> f64 {
+        self.hash_rate * (self.strength_scale * computer_strength).exp()
+    }
+
+    /// Outputs the computer gets through in one search interval.
+    pub fn budget(&self, computer_strength: f64) -> u64 {
+        (self.hashes_per_second(computer_strength) * self.search_interval).max(0.0) as u64
+    }
+}
+
+/// Parses `default` or `HASH_RATE:STRENGTH_SCALE:SEARCH_INTERVAL`.
+impl FromStr for CapacityModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "default" {
+            return Ok(Self::default());
+        }
+
+        let parts: Vec<f64> = s.split(':').map(number).collect::<Result<_, _>>()?;
+        match parts.as_slice() {
+            [hash_rate, strength_scale, search_interval]
+                if *hash_rate > 0.0 && *search_interval > 0.0 =>
+            {
+                Ok(Self {
+                    hash_rate: *hash_rate,
+                    strength_scale: *strength_scale,
+                    search_interval: *search_interval,
+                })
+            }
+            _ => Err(format!(
+                "Expected default or HASH_RATE:STRENGTH_SCALE:SEARCH_INTERVAL, got {}.",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_grows_with_strength() {
+        let model = CapacityModel::default();
+        assert_eq!(model.budget(0.0), 8_000);
+        assert!(model.budget(2.0) > model.budget(0.0));
+        assert!(model.budget(-2.0) < model.budget(0.0));
+    }
+
+    #[test]
+    fn test_parse_model() {
+        assert!("1000:0.5:16".parse::<CapacityModel>().is_ok());
+        assert!("0:0.5:16".parse::<CapacityModel>().is_err());
+        assert!("fast".parse::<CapacityModel>().is_err());
+    }
+}
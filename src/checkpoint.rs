@@ -0,0 +1,108 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::error::SimError;
+use crate::network::{Network, SimRng};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Everything needed to continue a run exactly where it stopped.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub network: Network,
+    pub rng: SimRng,
+    pub start_block_height: u64,
+    pub end_block_height: u64,
+}
+
+impl Checkpoint {
+    pub fn new(
+        network: Network,
+        rng: SimRng,
+        start_block_height: u64,
+        end_block_height: u64,
+    ) -> Self {
+        Self {
+            network,
+            rng,
+            start_block_height,
+            end_block_height,
+        }
+    }
+
+    /// Writes the checkpoint next to `path` first and renames it over, so an
+    /// interrupted write never clobbers the previous checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SimError> {
+        let path = path.as_ref();
+        let json = serde_json::to_string(self)?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SimError> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_resume_is_bit_identical() {
+        let mut rng = SimRng::seed_from_u64(11);
+        let mut network = Network::new();
+        network.create_stakers(&mut rng).unwrap();
+        let start = network.block_height();
+
+        let json =
+            serde_json::to_string(&Checkpoint::new(network, rng, start, start + 400)).unwrap();
+        let mut straight: Checkpoint = serde_json::from_str(&json).unwrap();
+        for _ in 0..400 {
+            straight.network.step(&mut straight.rng).unwrap();
+        }
+
+        let mut resumed: Checkpoint = serde_json::from_str(&json).unwrap();
+        for _ in 0..200 {
+            resumed.network.step(&mut resumed.rng).unwrap();
+        }
+        let path = std::env::temp_dir().join("veil_pos_simulation_checkpoint_test.json");
+        resumed.save(&path).unwrap();
+        let mut resumed = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        for _ in 0..200 {
+            resumed.network.step(&mut resumed.rng).unwrap();
+        }
+
+        assert_eq!(
+            serde_json::to_string(&straight).unwrap(),
+            serde_json::to_string(&resumed).unwrap()
+        );
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::amount::{self, Amount};
+use crate::denom::DenomStrategy;
+use crate::maturity::{Maturity, MaturityPhase};
+use crate::parse::number;
+use crate::staker::Staker;
+use crate::weight::{StakeModRule, StakeMods, WeightRule};
+use crate::zerocoin::ZerocoinModel;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// One consensus rule a hard fork changes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ConsensusChange {
+    Weight(WeightRule),
+    StakeMods(StakeMods),
+    Maturity(Maturity),
+    /// Zerocoin staking with the given model, or RingCT staking if `None`.
+    Zerocoin(Option<ZerocoinModel>),
+}
+
+/// Parses `weight:RULE`, `stake-mods:RULE,..` or `stake-mods:none`,
+/// `maturity:STAKE:INPUT_LOCK:TRANSFER`, `zerocoin:default|MODEL` or
+/// `ringct`.
+impl FromStr for ConsensusChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, spec) = match s.find(':') {
+            Some(pos) => (&s[..pos], &s[pos + 1..]),
+            None => (s, ""),
+        };
+        match kind {
+            "weight" => Ok(ConsensusChange::Weight(spec.parse()?)),
+            "stake-mods" if spec == "none" => Ok(ConsensusChange::StakeMods(StakeMods::default())),
+            "stake-mods" => Ok(ConsensusChange::StakeMods(StakeMods::new(
+                spec.split(',')
+                    .map(str::parse)
+                    .collect::<Result<Vec<StakeModRule>, _>>()?,
+            ))),
+            "maturity" => {
+                let phase: MaturityPhase = format!("0:{}", spec).parse()?;
+                Ok(ConsensusChange::Maturity(phase.maturity))
+            }
+            "zerocoin" => Ok(ConsensusChange::Zerocoin(Some(spec.parse()?))),
+            "ringct" if spec.is_empty() => Ok(ConsensusChange::Zerocoin(None)),
+            _ => Err(format!("Unknown consensus change {}.", s)),
+        }
+    }
+}
+
+impl fmt::Display for ConsensusChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConsensusChange::Weight(rule) => write!(f, "weight {:?}", rule),
+            ConsensusChange::StakeMods(mods) => write!(f, "stake mods {:?}", mods.rules()),
+            ConsensusChange::Maturity(maturity) => write!(
+                f,
+                "maturity {}/{}/{}",
+                maturity.stake, maturity.input_lock, maturity.transfer
+            ),
+            ConsensusChange::Zerocoin(Some(_)) => write!(f, "zerocoin"),
+            ConsensusChange::Zerocoin(None) => write!(f, "ringct"),
+        }
+    }
+}
+
+/// A consensus change that activates at `height`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Activation {
+    pub height: u64,
+    pub change: ConsensusChange,
+}
+
+/// Parses `HEIGHT:CHANGE`, see `ConsensusChange`.
+impl FromStr for Activation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pos = s
+            .find(':')
+            .ok_or_else(|| format!("Expected HEIGHT:CHANGE, got {}.", s))?;
+        let height = number(&s[..pos])?;
+        Ok(Self {
+            height,
+            change: s[pos + 1..].parse()?,
+        })
+    }
+}
+
+/// Hard forks in activation order. Changes sharing a height activate
+/// together.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ConsensusSchedule {
+    activations: Vec<Activation>,
+    /// Activations already applied.
+    applied: usize,
+}
+
+impl ConsensusSchedule {
+    pub fn new(mut activations: Vec<Activation>) -> Self {
+        activations.sort_by_key(|p| p.height);
+        Self {
+            activations,
+            applied: 0,
+        }
+    }
+
+    pub fn activations(&self) -> &[Activation] {
+        &self.activations
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.activations.is_empty()
+    }
+
+    /// The changes due by `block_height` that haven't been applied yet,
+    /// marked as applied.
+    pub fn activate(&mut self, block_height: u64) -> Vec<Activation> {
+        let due = self.activations[self.applied..]
+            .iter()
+            .take_while(|p| p.height <= block_height)
+            .cloned()
+            .collect::<Vec<_>>();
+        self.applied += due.len();
+        due
+    }
+}
+
+/// Where a staker stood when a consensus period began.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+struct Mark {
+    balance: Amount,
+    stake_count: u64,
+    rewards: Amount,
+}
+
+/// Stakers of one strategy over a consensus period.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PeriodRow {
+    pub denom_strat: DenomStrategy,
+    pub stakers: usize,
+    /// Held at the start of the period, or on joining.
+    pub balance: Amount,
+    pub blocks: u64,
+    pub rewards: Amount,
+}
+
+/// The blocks between two forks, labeled with the changes that opened it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Period {
+    pub start_height: u64,
+    pub end_height: u64,
+    pub changes: Vec<String>,
+    pub rows: Vec<PeriodRow>,
+}
+
+/// Tracks per staker results between forks, so runs can be compared before
+/// and after every rule change.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PeriodTracker {
+    start_height: u64,
+    changes: Vec<String>,
+    marks: Vec<Mark>,
+    closed: Vec<Period>,
+}
+
+impl PeriodTracker {
+    pub fn new(stakers: &[Staker], block_height: u64) -> Self {
+        Self {
+            start_height: block_height,
+            changes: vec!["start".to_owned()],
+            marks: stakers.iter().map(mark).collect(),
+            closed: Vec::new(),
+        }
+    }
+
+    /// Closes the running period at `block_height` and opens one labeled with
+    /// `changes`.
+    pub fn fork(&mut self, stakers: &[Staker], block_height: u64, changes: Vec<String>) {
+        let period = self.period(stakers, block_height);
+        self.closed.push(period);
+        self.start_height = block_height;
+        self.changes = changes;
+        self.marks = stakers.iter().map(mark).collect();
+    }
+
+    /// Every closed period and the running one up to `block_height`.
+    pub fn report(&self, stakers: &[Staker], block_height: u64) -> ConsensusReport {
+        let mut periods = self.closed.clone();
+        periods.push(self.period(stakers, block_height));
+        ConsensusReport { periods }
+    }
+
+    fn period(&self, stakers: &[Staker], block_height: u64) -> Period {
+        let mut rows: BTreeMap<DenomStrategy, PeriodRow> = BTreeMap::new();
+        for (pos, staker) in stakers.iter().enumerate() {
+            if !staker.is_active() {
+                continue;
+            }
+            let end = mark(staker);
+            // Stakers that joined during the period start from their balance
+            // on joining.
+            let start = self.marks.get(pos).copied().unwrap_or(Mark {
+                stake_count: 0,
+                rewards: Amount::ZERO,
+                balance: staker.start_balance(),
+            });
+            let row = rows
+                .entry(staker.denom_strat())
+                .or_insert_with(|| PeriodRow {
+                    denom_strat: staker.denom_strat(),
+                    stakers: 0,
+                    balance: Amount::ZERO,
+                    blocks: 0,
+                    rewards: Amount::ZERO,
+                });
+            row.stakers += 1;
+            row.balance += start.balance;
+            row.blocks += end.stake_count.saturating_sub(start.stake_count);
+            row.rewards += end.rewards.saturating_sub(start.rewards);
+        }
+        Period {
+            start_height: self.start_height,
+            end_height: block_height,
+            changes: self.changes.clone(),
+            rows: rows.into_values().collect(),
+        }
+    }
+}
+
+fn mark(staker: &Staker) -> Mark {
+    Mark {
+        balance: staker.denoms().balance(),
+        stake_count: staker.stake_count(),
+        rewards: staker.rewards_earned(),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConsensusReport {
+    pub periods: Vec<Period>,
+}
+
+impl fmt::Display for ConsensusReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for period in &self.periods {
+            writeln!(
+                f,
+                "Blocks {} to {} ({}):",
+                period.start_height,
+                period.end_height,
+                period.changes.join(", ")
+            )?;
+            let balance: Amount = period.rows.iter().map(|p| p.balance).sum();
+            let blocks: u64 = period.rows.iter().map(|p| p.blocks).sum();
+            writeln!(
+                f,
+                "{:>18} {:>8} {:>9} {:>8} {:>12} {:>9}",
+                "strategy", "stakers", "balance%", "blocks%", "rewards", "return%"
+            )?;
+            for row in &period.rows {
+                writeln!(
+                    f,
+                    "{:>18} {:>8} {:>9.3} {:>8.3} {:>12} {:>9.4}",
+                    format!("{:?}", row.denom_strat),
+                    row.stakers,
+                    share(row.balance.to_sat(), balance.to_sat()) * 100.0,
+                    share(row.blocks, blocks) * 100.0,
+                    row.rewards.to_sat() / amount::COIN,
+                    share(row.rewards.to_sat(), row.balance.to_sat()) * 100.0
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn share(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_activations() {
+        let activation: Activation = "300_000:maturity:60:2000:100".parse().unwrap();
+        assert_eq!(activation.height, 300_000);
+        assert_eq!(activation.change.to_string(), "maturity 60/2000/100");
+        assert_eq!(
+            "1:ringct".parse::<Activation>().unwrap().change,
+            ConsensusChange::Zerocoin(None)
+        );
+        assert!(
+            "1:stake-mods:young:10:0.5,reward:0.8"
+                .parse::<Activation>()
+                .is_ok()
+        );
+        assert!("1:zerocoin:default".parse::<Activation>().is_ok());
+        assert!("ringct".parse::<Activation>().is_err());
+        assert!("1:hashcash".parse::<Activation>().is_err());
+    }
+
+    #[test]
+    fn test_schedule_activates_once() {
+        let mut schedule = ConsensusSchedule::new(vec![
+            "20:ringct".parse().unwrap(),
+            "10:weight:linear".parse().unwrap(),
+            "10:stake-mods:none".parse().unwrap(),
+        ]);
+        assert!(schedule.activate(9).is_empty());
+        assert_eq!(schedule.activate(10).len(), 2);
+        assert!(schedule.activate(15).is_empty());
+        assert_eq!(schedule.activate(25).len(), 1);
+        assert!(schedule.activate(30).is_empty());
+    }
+}
@@ -22,8 +22,10 @@
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
 
-use crate::amount;
+use crate::amount::{self, Amount};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
 pub static DENOM_BRACKET_MOD: f64 = 0.0;
 pub static DENOM_MIN: u64 = 1u64 << 32;
@@ -33,57 +35,225 @@ pub static DENOM_SHIFT: u32 = 2;
 pub static DENOM_THRESHOLD_MIN: u64 = 0;
 pub static DENOM_THRESHOLD_MAX: u64 = 20_000;
 
-#[derive(Debug)]
-enum DenomValue {
+/// The fixed Zerocoin denominations, in whole coins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenomValue {
     D10 = 10,
     D100 = 100,
     D1000 = 1_000,
     D10000 = 10_000,
 }
 
-enum DenomStrategy {
-    Only10,
-    Only100,
-    Only1000,
-    Only10000,
-    Half10And100,
-    Half100And1000,
-    Half1000And10000,
+impl DenomValue {
+    /// Smallest first, so strategy brackets 0 to 3 index into it.
+    pub const ALL: [DenomValue; 4] = [
+        DenomValue::D10,
+        DenomValue::D100,
+        DenomValue::D1000,
+        DenomValue::D10000,
+    ];
+
+    pub fn value(self) -> Amount {
+        Amount::from_coins(self as u64)
+    }
+}
+
+/// How a wallet splits its balance into outputs. Every output a strategy
+/// creates sits at the bottom of a weight bracket, so the strategies differ
+/// in how many outputs they end up holding rather than in weight per coin.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DenomStrategy {
+    OnlySmall,
+    OnlyMedium,
+    OnlyLarge,
+    OnlyHuge,
+    HalfSmallAndMedium,
+    HalfMediumAndLarge,
+    HalfLargeAndHuge,
     AllEqual,
     Optimal,
 }
 
+impl DenomStrategy {
+    pub const ALL: [DenomStrategy; 9] = [
+        DenomStrategy::OnlySmall,
+        DenomStrategy::OnlyMedium,
+        DenomStrategy::OnlyLarge,
+        DenomStrategy::OnlyHuge,
+        DenomStrategy::HalfSmallAndMedium,
+        DenomStrategy::HalfMediumAndLarge,
+        DenomStrategy::HalfLargeAndHuge,
+        DenomStrategy::AllEqual,
+        DenomStrategy::Optimal,
+    ];
+
+    /// Maps the numeric strategy stored in a wallet to a strategy. Anything
+    /// out of range is treated as `Optimal`.
+    pub fn from_index(index: u64) -> Self {
+        Self::ALL
+            .get(index as usize)
+            .copied()
+            .unwrap_or(DenomStrategy::Optimal)
+    }
+
+    /// The brackets this strategy fills. `Optimal` picks its bracket at
+    /// runtime, see `Wallet`.
+    pub fn brackets(self) -> &'static [u32] {
+        use DenomStrategy::*;
+        match self {
+            OnlySmall => &[0],
+            OnlyMedium => &[1],
+            OnlyLarge => &[2],
+            OnlyHuge => &[3],
+            HalfSmallAndMedium => &[0, 1],
+            HalfMediumAndLarge => &[1, 2],
+            HalfLargeAndHuge => &[2, 3],
+            AllEqual => &[0, 1, 2, 3],
+            Optimal => &[],
+        }
+    }
+}
+
+/// Parses a strategy name, ignoring case.
+impl FromStr for DenomStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|p| format!("{:?}", p).eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("Unknown strategy {}.", s))
+    }
+}
+
+/// Number of weight brackets between `DENOM_MIN` and `DENOM_MAX`.
+pub fn bracket_count() -> u32 {
+    (DENOM_MAX.trailing_zeros() - DENOM_MIN.trailing_zeros()) / DENOM_SHIFT + 1
+}
+
+/// Lower bound of the given bracket. Outputs must be strictly above it to
+/// earn the bracket's weight, except at `DENOM_MIN`, which is in the first.
+pub fn bracket_floor(bracket: u32) -> u64 {
+    DENOM_MIN << (DENOM_SHIFT * bracket)
+}
+
+/// Smallest output value that earns the full weight of the given bracket.
+pub fn bracket_target(bracket: u32) -> u64 {
+    bracket_floor(bracket) + 1
+}
+
+/// The bracket an output of `value` weighs in: the highest one whose floor it
+/// is strictly above. Values at a floor fall in the bracket below it, except
+/// `DENOM_MIN` itself, which is in the first. Values past the top bracket stay
+/// in it, and anything below `DENOM_MIN` has no bracket and no weight.
+pub fn bracket_of(value: u64) -> Option<u32> {
+    if value < DENOM_MIN {
+        return None;
+    }
+    let bits = 63 - (value - 1).max(DENOM_MIN).leading_zeros();
+    let bracket = (bits - DENOM_MIN.trailing_zeros()) / DENOM_SHIFT;
+    Some(bracket.min(bracket_count() - 1))
+}
+
+/// Weight of every output in `bracket`, less `bracket_mod` for each bracket
+/// above the first. Never negative.
+pub fn weight_of(bracket: u32, bracket_mod: f64) -> u64 {
+    let modifier = (1.0 - bracket as f64 * bracket_mod).max(0.0);
+    (bracket_target(bracket) as f64 * modifier) as u64
+}
+
+/// One row of `bracket_table`. Outputs above `floor`, or at it in the first
+/// row, and up to `ceiling` weigh `weight`.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct BracketRow {
+    pub bracket: u32,
+    pub floor: u64,
+    pub ceiling: u64,
+    pub weight: u64,
+}
+
+/// Every weight bracket from `DENOM_MIN` to `MAX_MONEY` under `bracket_mod`.
+pub fn bracket_table(bracket_mod: f64) -> BracketTable {
+    let rows = (0..bracket_count())
+        .map(|bracket| BracketRow {
+            bracket,
+            floor: bracket_floor(bracket),
+            ceiling: if bracket + 1 < bracket_count() {
+                bracket_floor(bracket + 1)
+            } else {
+                amount::MAX_MONEY
+            },
+            weight: weight_of(bracket, bracket_mod),
+        })
+        .collect();
+    BracketTable { rows }
+}
+
+fn coins(value: u64) -> f64 {
+    value as f64 / amount::COIN as f64
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct BracketTable {
+    pub rows: Vec<BracketRow>,
+}
+
+impl fmt::Display for BracketTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>7} {:>20} {:>20} {:>20}",
+            "bracket", "above (coins)", "up to (coins)", "weight (coins)"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:>7} {:>20.8} {:>20.8} {:>20.8}",
+                row.bracket,
+                coins(row.floor),
+                coins(row.ceiling),
+                coins(row.weight),
+            )?;
+        }
+        Ok(())
+    }
+}
+
 pub struct DenomBuilder {
-    value: u64,
+    value: Amount,
     stake_mod: f64,
     is_stake: bool,
     is_mature: bool,
     created_height: u64,
     mature_height: u64,
+    is_zerocoin: bool,
+}
+
+impl Default for DenomBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DenomBuilder {
     pub fn new() -> Self {
         Self {
-            value: 0,
+            value: Amount::ZERO,
             stake_mod: 1.0,
             is_stake: false,
             is_mature: false,
             created_height: 0,
             mature_height: 0,
+            is_zerocoin: false,
         }
     }
 
-    pub fn value(mut self, v: u64) -> Self {
-        assert!(amount::money_range(v));
+    pub fn value(mut self, v: Amount) -> Self {
         self.value = v;
         self
     }
 
-    pub fn base_value(mut self, v: f64) -> Self {
-        self.value((v * amount::COIN as f64) as u64)
-    }
-
     pub fn stake_mod(mut self, v: f64) -> Self {
         self.stake_mod = v;
         self
@@ -109,6 +279,11 @@ impl DenomBuilder {
         self
     }
 
+    pub fn zerocoin(mut self, v: bool) -> Self {
+        self.is_zerocoin = v;
+        self
+    }
+
     pub fn build(self) -> Denom {
         Denom {
             value: self.value,
@@ -117,37 +292,40 @@ impl DenomBuilder {
             is_mature: self.is_mature,
             created_height: self.created_height,
             mature_height: self.mature_height,
+            is_zerocoin: self.is_zerocoin,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Denom {
-    value: u64,
+    value: Amount,
     stake_mod: f64,
     is_stake: bool,
     is_mature: bool,
     created_height: u64,
     mature_height: u64,
+    /// A minted zerocoin rather than a basecoin output.
+    is_zerocoin: bool,
 }
 
 impl Denom {
     pub fn new(
-        sat_value: u64,
+        value: Amount,
         stake_mod: f64,
         is_stake: bool,
         is_mature: bool,
         created_height: u64,
         mature_height: u64,
     ) -> Self {
-        assert!(amount::money_range(sat_value));
         Self {
-            value: sat_value,
+            value,
             stake_mod,
             is_stake,
             is_mature,
             created_height,
             mature_height,
+            is_zerocoin: false,
         }
     }
 
@@ -160,10 +338,10 @@ impl Denom {
     }
 
     pub fn base_value(&self) -> f64 {
-        self.value as f64 / amount::COIN as f64
+        self.value.to_coins()
     }
 
-    pub fn value(&self) -> u64 {
+    pub fn value(&self) -> Amount {
         self.value
     }
 
@@ -171,6 +349,10 @@ impl Denom {
         self.stake_mod
     }
 
+    pub fn set_stake_mod(&mut self, stake_mod: f64) {
+        self.stake_mod = stake_mod;
+    }
+
     pub fn is_stake(&self) -> bool {
         self.is_stake
     }
@@ -187,27 +369,31 @@ impl Denom {
         self.mature_height
     }
 
+    pub fn is_zerocoin(&self) -> bool {
+        self.is_zerocoin
+    }
+
     pub fn weight(&self) -> u64 {
-        let mut bracket = DENOM_MIN;
-        let mut modifier = 0.0;
-        let mut weight = 0;
-        if self.value > DENOM_MIN {
-            while bracket <= DENOM_MAX {
-                if self.value > bracket && self.value < bracket << 2 {
-                    let weight_modifier = 1.0 - (modifier * DENOM_BRACKET_MOD);
-                    weight = ((bracket + 1) as f64 * weight_modifier) as u64;
-
-                    break;
-                }
-                bracket <<= 2;
-                modifier += 1.0;
-            }
-        }
-        weight
+        self.bracket_weight(DENOM_BRACKET_MOD)
+    }
+
+    /// Weight using `bracket_mod` as the per bracket penalty in place of
+    /// `DENOM_BRACKET_MOD`. See `bracket_of` for which bracket applies. Never
+    /// more than the value, which only caps an output of exactly `DENOM_MIN`.
+    pub fn bracket_weight(&self, bracket_mod: f64) -> u64 {
+        let value = self.value.to_sat();
+        bracket_of(value).map_or(0, |bracket| weight_of(bracket, bracket_mod).min(value))
     }
 
+    /// Whether the output falls in a bracket and so has any weight.
     pub fn can_stake(&self) -> bool {
-        self.value() >= DENOM_MIN
+        self.value.to_sat() >= DENOM_MIN
+    }
+
+    /// Whether this output is exactly one of the bracket targets a wallet
+    /// strategy creates.
+    pub fn is_target(&self) -> bool {
+        (0..bracket_count()).any(|bracket| self.value.to_sat() == bracket_target(bracket))
     }
 }
 
@@ -215,30 +401,144 @@ impl Denom {
 mod tests {
     use super::*;
     use crate::amount;
+    use proptest::prelude::*;
 
     #[test]
     fn test_denom_no_weight() {
-        let denom = Denom::builder().value(DENOM_MIN).build();
+        let denom = Denom::builder()
+            .value(Amount::from_sat(DENOM_MIN - 1))
+            .build();
         assert_eq!(denom.weight(), 0);
+        assert!(!denom.can_stake());
+    }
+
+    #[test]
+    fn test_bracket_boundaries() {
+        assert_eq!(bracket_of(DENOM_MIN - 1), None);
+        assert_eq!(bracket_of(DENOM_MIN), Some(0));
+        let denom = Denom::builder().value(Amount::from_sat(DENOM_MIN)).build();
+        assert!(denom.can_stake());
+        assert_eq!(denom.weight(), DENOM_MIN);
+
+        assert_eq!(bracket_of(1u64 << 34), Some(0));
+        assert_eq!(bracket_of((1u64 << 34) + 1), Some(1));
+        assert_eq!(bracket_of(DENOM_MAX << 2), Some(bracket_count() - 1));
+        assert_eq!(bracket_of(amount::MAX_MONEY), Some(bracket_count() - 1));
     }
 
     #[test]
     fn test_denom_min_weight() {
-        let denom = Denom::builder().value(DENOM_MIN + 1).build();
+        let denom = Denom::builder()
+            .value(Amount::from_sat(DENOM_MIN + 1))
+            .build();
         assert_eq!(denom.weight(), 4_294_967_297);
     }
 
     #[test]
     fn test_denom_next_weight() {
         let shift: u64 = 1u64 << 34;
-        let denom = Denom::builder().value(shift + 1).build();
+        let denom = Denom::builder().value(Amount::from_sat(shift + 1)).build();
         assert_eq!(denom.weight(), 17_179_869_185);
     }
 
     #[test]
-    #[should_panic]
-    fn test_denom_builder_money_range() {
-        Denom::builder().value(amount::MAX_MONEY + 1).build();
+    fn test_bracket_mod_penalizes_larger_brackets() {
+        let small = Denom::builder()
+            .value(Amount::from_sat(bracket_target(0)))
+            .build();
+        let large = Denom::builder()
+            .value(Amount::from_sat(bracket_target(2)))
+            .build();
+        assert_eq!(small.bracket_weight(0.1), small.weight());
+        assert_eq!(
+            large.bracket_weight(0.1),
+            (bracket_target(2) as f64 * 0.8) as u64
+        );
+    }
+
+    #[test]
+    fn test_bracket_target_weight() {
+        for bracket in 0..bracket_count() {
+            let denom = Denom::builder()
+                .value(Amount::from_sat(bracket_target(bracket)))
+                .build();
+            assert_eq!(denom.weight(), bracket_target(bracket));
+            assert!(denom.is_target());
+        }
+    }
+
+    #[test]
+    fn test_boundaries_fall_in_lower_bracket() {
+        let boundary = Denom::builder().value(Amount::from_sat(1u64 << 34)).build();
+        assert_eq!(bracket_of(1u64 << 34), Some(0));
+        assert_eq!(boundary.weight(), bracket_target(0));
+
+        let top = Denom::builder().value(Amount::MAX_MONEY).build();
+        assert_eq!(bracket_of(DENOM_MAX << 2), Some(bracket_count() - 1));
+        assert_eq!(top.weight(), bracket_target(bracket_count() - 1));
+    }
+
+    #[test]
+    fn test_bracket_table_covers_money_range() {
+        let table = bracket_table(DENOM_BRACKET_MOD);
+        assert_eq!(table.rows.len() as u32, bracket_count());
+        assert_eq!(table.rows[0].floor, DENOM_MIN);
+        assert_eq!(table.rows.last().unwrap().ceiling, amount::MAX_MONEY);
+        for pair in table.rows.windows(2) {
+            assert_eq!(pair[0].ceiling, pair[1].floor);
+        }
+        assert!(
+            bracket_table(0.2)
+                .rows
+                .iter()
+                .all(|p| p.weight <= p.ceiling)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_every_value_has_a_bracket(value in DENOM_MIN..=amount::MAX_MONEY) {
+            let denom = Denom::builder().value(Amount::from_sat(value)).build();
+            prop_assert!(bracket_of(value).is_some());
+            prop_assert!(denom.can_stake());
+            let bracket = bracket_of(value).unwrap();
+            let row = bracket_table(DENOM_BRACKET_MOD).rows[bracket as usize];
+            prop_assert!(row.floor < value || value == DENOM_MIN);
+            prop_assert!(value <= row.ceiling);
+            prop_assert_eq!(denom.weight(), row.weight.min(value));
+        }
+
+        #[test]
+        fn prop_weight_never_exceeds_value(value in 0..=amount::MAX_MONEY, bracket_mod in 0.0..1.0f64) {
+            let denom = Denom::builder().value(Amount::from_sat(value)).build();
+            prop_assert!(denom.bracket_weight(bracket_mod) <= value);
+        }
+
+        #[test]
+        fn prop_weight_is_monotonic(a in 0..=amount::MAX_MONEY, b in 0..=amount::MAX_MONEY) {
+            let (low, high) = (a.min(b), a.max(b));
+            prop_assert!(
+                Denom::builder().value(Amount::from_sat(low)).build().weight()
+                    <= Denom::builder().value(Amount::from_sat(high)).build().weight()
+            );
+        }
+
+        #[test]
+        fn prop_bracket_boundaries(bracket in 0..bracket_count()) {
+            let floor = bracket_floor(bracket);
+            prop_assert_eq!(bracket_of(floor), Some(bracket.saturating_sub(1)));
+            prop_assert_eq!(bracket_of(floor + 1), Some(bracket));
+        }
+    }
+
+    #[test]
+    fn test_strategy_from_index() {
+        assert_eq!(DenomStrategy::from_index(0), DenomStrategy::OnlySmall);
+        assert_eq!(
+            DenomStrategy::from_index(6),
+            DenomStrategy::HalfLargeAndHuge
+        );
+        assert_eq!(DenomStrategy::from_index(42), DenomStrategy::Optimal);
     }
 
     #[test]
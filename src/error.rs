@@ -0,0 +1,142 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+use crate::amount;
+use crate::invariant::Violation;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Everything the simulation can fail with instead of panicking.
+#[derive(Debug)]
+pub enum SimError {
+    /// An amount outside `0..=MAX_MONEY`, in sats.
+    OutOfRange(u64),
+    /// A weighted pick had nothing with any weight to pick from.
+    EmptySelection {
+        selection: &'static str,
+        block_height: u64,
+    },
+    InvalidConfig(String),
+    /// An invariant check failed at `block_height`, having last passed at
+    /// `last_held`.
+    Invariant {
+        block_height: u64,
+        last_held: Option<u64>,
+        violation: Violation,
+    },
+    Io(io::Error),
+    Serialization(serde_json::Error),
+}
+
+impl SimError {
+    /// Checks that `value` is a valid amount.
+    pub fn check_range(value: u64) -> Result<u64, Self> {
+        if amount::money_range(value) {
+            Ok(value)
+        } else {
+            Err(SimError::OutOfRange(value))
+        }
+    }
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimError::OutOfRange(value) => write!(
+                f,
+                "Amount of {} coins is out of range.",
+                *value as f64 / amount::COIN as f64
+            ),
+            SimError::EmptySelection {
+                selection,
+                block_height,
+            } => write!(f, "No {} to pick at block {}.", selection, block_height),
+            SimError::InvalidConfig(message) => write!(f, "{}", message),
+            SimError::Invariant {
+                block_height,
+                last_held,
+                violation,
+            } => {
+                // Checks run between blocks, so the culprit was staked after
+                // the last passing check and before the failing one.
+                let last = block_height.saturating_sub(1);
+                match last_held {
+                    Some(first) if *first >= last => {
+                        write!(f, "Invariant broken at block {}: {}.", last, violation)
+                    }
+                    Some(first) => write!(
+                        f,
+                        "Invariant broken between blocks {} and {}: {}.",
+                        first, last, violation
+                    ),
+                    None => write!(
+                        f,
+                        "Invariant broken at or before block {}: {}.",
+                        last, violation
+                    ),
+                }
+            }
+            SimError::Io(e) => write!(f, "{}", e),
+            SimError::Serialization(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for SimError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SimError::Io(e) => Some(e),
+            SimError::Serialization(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SimError {
+    fn from(e: io::Error) -> Self {
+        SimError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SimError {
+    fn from(e: serde_json::Error) -> Self {
+        SimError::Serialization(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_range() {
+        assert_eq!(
+            SimError::check_range(amount::MAX_MONEY).unwrap(),
+            amount::MAX_MONEY
+        );
+        let e = SimError::check_range(amount::MAX_MONEY + 1).unwrap_err();
+        assert!(matches!(e, SimError::OutOfRange(_)));
+        assert!(e.source().is_none());
+    }
+}
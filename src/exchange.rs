@@ -0,0 +1,208 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+use crate::amount::{self, Amount};
+use crate::denom::DenomStrategy;
+use crate::parse::number;
+use crate::staker::Staker;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Computer strength of an exchange's servers.
+pub static EXCHANGE_STRENGTH: f64 = 3.0;
+
+/// An exchange or custodian holding coins on behalf of its users. Its hot
+/// wallet is a staker that may or may not stake, and regular stakers deposit
+/// into it and withdraw from it at the given rates per block.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Exchange {
+    /// Share of the starting supply held by the exchange.
+    pub share: f64,
+    pub staking: bool,
+    pub strategy: DenomStrategy,
+    pub deposit_rate: f64,
+    pub withdrawal_rate: f64,
+    /// Id of the staker holding the exchange's wallet, once created.
+    staker: Option<u64>,
+    deposits: u64,
+    withdrawals: u64,
+    deposited: Amount,
+    withdrawn: Amount,
+}
+
+impl Exchange {
+    pub fn new(
+        share: f64,
+        staking: bool,
+        strategy: DenomStrategy,
+        deposit_rate: f64,
+        withdrawal_rate: f64,
+    ) -> Self {
+        Self {
+            share,
+            staking,
+            strategy,
+            deposit_rate,
+            withdrawal_rate,
+            staker: None,
+            deposits: 0,
+            withdrawals: 0,
+            deposited: Amount::ZERO,
+            withdrawn: Amount::ZERO,
+        }
+    }
+
+    pub fn staker(&self) -> Option<u64> {
+        self.staker
+    }
+
+    pub fn set_staker(&mut self, staker: u64) {
+        self.staker = Some(staker);
+    }
+
+    pub fn add_deposit(&mut self, value: Amount) {
+        self.deposits += 1;
+        self.deposited += value;
+    }
+
+    pub fn add_withdrawal(&mut self, value: Amount) {
+        self.withdrawals += 1;
+        self.withdrawn += value;
+    }
+}
+
+/// Parses `SHARE:stake|hold:STRATEGY:DEPOSITS:WITHDRAWALS`.
+impl FromStr for Exchange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() != 5 {
+            return Err(format!(
+                "Expected SHARE:stake|hold:STRATEGY:DEPOSITS:WITHDRAWALS, got {}.",
+                s
+            ));
+        }
+        let share: f64 = number(parts[0])?;
+        if !(0.0..1.0).contains(&share) {
+            return Err(format!("Share must be between 0 and 1, got {}.", share));
+        }
+        let staking = match parts[1] {
+            "stake" => true,
+            "hold" => false,
+            other => return Err(format!("Expected stake or hold, got {}.", other)),
+        };
+
+        Ok(Exchange::new(
+            share,
+            staking,
+            parts[2].parse()?,
+            number(parts[3])?,
+            number(parts[4])?,
+        ))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExchangeRow {
+    pub staker: u64,
+    pub staking: bool,
+    pub percent_total: f64,
+    pub denom_count: u64,
+    pub stake_count: u64,
+    pub deposits: u64,
+    pub withdrawals: u64,
+    pub deposited: Amount,
+    pub withdrawn: Amount,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExchangeReport {
+    pub rows: Vec<ExchangeRow>,
+}
+
+impl ExchangeReport {
+    pub fn new(exchanges: &[Exchange], stakers: &[Staker]) -> Self {
+        let rows = exchanges
+            .iter()
+            .filter_map(|exchange| {
+                let staker = stakers.iter().find(|p| Some(p.id()) == exchange.staker)?;
+                Some(ExchangeRow {
+                    staker: staker.id(),
+                    staking: exchange.staking,
+                    percent_total: staker.percent_total(),
+                    denom_count: staker.denoms().count(),
+                    stake_count: staker.stake_count(),
+                    deposits: exchange.deposits,
+                    withdrawals: exchange.withdrawals,
+                    deposited: exchange.deposited,
+                    withdrawn: exchange.withdrawn,
+                })
+            })
+            .collect();
+
+        Self { rows }
+    }
+}
+
+impl fmt::Display for ExchangeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>6} {:>8} {:>8} {:>8} {:>8} {:>9} {:>12} {:>12}",
+            "staker", "staking", "% total", "denoms", "blocks", "deposits", "in", "out"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:>6} {:>8} {:>8.4} {:>8} {:>8} {:>9} {:>12} {:>12}",
+                row.staker,
+                row.staking,
+                row.percent_total * 100.0,
+                row.denom_count,
+                row.stake_count,
+                row.deposits,
+                row.deposited.to_sat() / amount::COIN,
+                row.withdrawn.to_sat() / amount::COIN
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exchange() {
+        let exchange: Exchange = "0.2:hold:onlysmall:0.5:0.4".parse().unwrap();
+        assert!(!exchange.staking);
+        assert_eq!(exchange.strategy, DenomStrategy::OnlySmall);
+        assert_eq!(exchange.withdrawal_rate, 0.4);
+        assert!("1.2:hold:onlysmall:0.5:0.4".parse::<Exchange>().is_err());
+        assert!("0.2:lend:onlysmall:0.5:0.4".parse::<Exchange>().is_err());
+        assert!("0.2:stake:onlysmall".parse::<Exchange>().is_err());
+    }
+}
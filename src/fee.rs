@@ -0,0 +1,176 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+use crate::amount::{self, Amount};
+use crate::parse::number;
+use crate::wallet::Sent;
+use rand::Rng;
+use rand_distr::{Distribution, LogNormal};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Transaction fees by size. A transaction weighs `input_size` bytes per
+/// input and `output_size` per output, and pays a fee rate drawn from a log
+/// normal distribution around `median_rate`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct FeeModel {
+    pub input_size: u64,
+    pub output_size: u64,
+    /// Median fee rate in sats per byte.
+    pub median_rate: f64,
+    /// Spread of the fee rate, the sigma of its logarithm.
+    pub rate_sigma: f64,
+}
+
+impl Default for FeeModel {
+    fn default() -> Self {
+        Self {
+            input_size: 5_000,
+            output_size: 1_500,
+            median_rate: 10.0,
+            rate_sigma: 1.0,
+        }
+    }
+}
+
+impl FeeModel {
+    pub fn size(&self, sent: &Sent) -> u64 {
+        sent.inputs * self.input_size + sent.outputs * self.output_size
+    }
+
+    /// Draws the fee for a transaction, never more than it moved.
+    pub fn fee<R: Rng + ?Sized>(&self, sent: &Sent, rng: &mut R) -> Amount {
+        let rate = LogNormal::new(self.median_rate.ln(), self.rate_sigma)
+            .unwrap()
+            .sample(rng);
+        Amount::try_from_sat((self.size(sent) as f64 * rate) as u64)
+            .map_or(sent.value, |fee| fee.min(sent.value))
+    }
+}
+
+/// Parses `default` or `INPUT_BYTES:OUTPUT_BYTES:MEDIAN_RATE:SIGMA`.
+impl FromStr for FeeModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "default" {
+            return Ok(Self::default());
+        }
+
+        let parts: Vec<f64> = s.split(':').map(number).collect::<Result<_, _>>()?;
+        match parts.as_slice() {
+            [input_size, output_size, median_rate, rate_sigma]
+                if *median_rate > 0.0 && *rate_sigma >= 0.0 =>
+            {
+                Ok(Self {
+                    input_size: *input_size as u64,
+                    output_size: *output_size as u64,
+                    median_rate: *median_rate,
+                    rate_sigma: *rate_sigma,
+                })
+            }
+            _ => Err(format!(
+                "Expected default or INPUT_BYTES:OUTPUT_BYTES:MEDIAN_RATE:SIGMA, got {}.",
+                s
+            )),
+        }
+    }
+}
+
+/// Block producer income over one reward era.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct EraIncome {
+    pub era: u64,
+    pub blocks: u64,
+    pub subsidy: Amount,
+    pub fees: Amount,
+}
+
+impl EraIncome {
+    pub fn fee_share(&self) -> f64 {
+        let total = self.subsidy + self.fees;
+        if total.is_zero() {
+            0.0
+        } else {
+            self.fees.to_sat() as f64 / total.to_sat() as f64
+        }
+    }
+}
+
+/// Subsidy against fees for every era the run went through.
+#[derive(Debug, Serialize)]
+pub struct FeeReport {
+    pub eras: Vec<EraIncome>,
+}
+
+impl fmt::Display for FeeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>5} {:>10} {:>14} {:>14} {:>8}",
+            "era", "blocks", "subsidy", "fees", "fee%"
+        )?;
+        for era in &self.eras {
+            writeln!(
+                f,
+                "{:>5} {:>10} {:>14} {:>14.2} {:>7.3}%",
+                era.era,
+                era.blocks,
+                era.subsidy.to_sat() / amount::COIN,
+                era.fees.to_coins(),
+                era.fee_share() * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn test_fee_scales_with_size() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let model = FeeModel {
+            rate_sigma: 0.0,
+            ..FeeModel::default()
+        };
+        let sent = Sent {
+            value: Amount::COIN,
+            inputs: 2,
+            outputs: 2,
+        };
+        assert_eq!(model.size(&sent), 13_000);
+        assert_eq!(model.fee(&sent, &mut rng), Amount::from_sat(130_000));
+
+        let dust = Sent {
+            value: Amount::from_sat(10),
+            ..sent
+        };
+        assert_eq!(model.fee(&dust, &mut rng), dust.value);
+        assert!("1:2:0:1".parse::<FeeModel>().is_err());
+    }
+}
@@ -0,0 +1,359 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::amount::Amount;
+use crate::checkpoint::Checkpoint;
+use crate::denom::DenomStrategy;
+use crate::error::SimError;
+use crate::network::{Network, RewardSchedule};
+use crate::parse::number;
+use crate::weight::WeightRule;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// A change applied to one branch of a fork.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub enum Modification {
+    RewardSchedule(RewardSchedule),
+    DenomStrategy {
+        staker: u64,
+        strategy: DenomStrategy,
+    },
+    InjectWhale {
+        balance: Amount,
+        strategy: DenomStrategy,
+        computer_strength: f64,
+    },
+    DenomBracketMod(f64),
+    WeightRule(WeightRule),
+}
+
+impl Modification {
+    pub fn apply(&self, network: &mut Network) -> Result<(), SimError> {
+        match self {
+            Modification::RewardSchedule(schedule) => network.set_reward_schedule(*schedule),
+            Modification::DenomStrategy { staker, strategy } => {
+                let block_height = network.block_height();
+                network
+                    .staker_mut(*staker)
+                    .ok_or_else(|| {
+                        SimError::InvalidConfig(format!("No staker with id {}.", staker))
+                    })?
+                    .set_denom_strat(*strategy, block_height)?;
+            }
+            Modification::InjectWhale {
+                balance,
+                strategy,
+                computer_strength,
+            } => {
+                network.add_staker(*balance, *strategy, *computer_strength)?;
+            }
+            Modification::DenomBracketMod(bracket_mod) => {
+                network.set_denom_bracket_mod(*bracket_mod)?
+            }
+            Modification::WeightRule(weight_rule) => network.set_weight_rule(*weight_rule),
+        }
+        Ok(())
+    }
+}
+
+/// Parses the command line form of a modification:
+///
+/// - `reward:INITIAL:REDUCTION:INTERVAL:FLOOR` (whole coins, blocks)
+/// - `strategy:STAKER_ID:STRATEGY`
+/// - `whale:COINS:STRATEGY[:COMPUTER_STRENGTH]`
+/// - `bracket-mod:VALUE`, for the bracket and coin-age weight rules
+/// - `weight:RULE`, see `WeightRule`
+impl FromStr for Modification {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(rule) = s.strip_prefix("weight:") {
+            return Ok(Modification::WeightRule(rule.parse()?));
+        }
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts.as_slice() {
+            ["reward", initial, reduction, interval, floor] => {
+                Ok(Modification::RewardSchedule(RewardSchedule {
+                    initial: number(initial)?,
+                    reduction: number(reduction)?,
+                    interval: number(interval)?,
+                    floor: number(floor)?,
+                }))
+            }
+            ["strategy", staker, strategy] => Ok(Modification::DenomStrategy {
+                staker: number(staker)?,
+                strategy: parse_strategy(strategy)?,
+            }),
+            ["whale", coins, strategy] | ["whale", coins, strategy, _] => {
+                let computer_strength = match parts.get(3) {
+                    Some(strength) => number(strength)?,
+                    None => 0.0,
+                };
+                Ok(Modification::InjectWhale {
+                    balance: number(coins)?,
+                    strategy: parse_strategy(strategy)?,
+                    computer_strength,
+                })
+            }
+            ["bracket-mod", value] => Ok(Modification::DenomBracketMod(number(value)?)),
+            _ => Err(format!("Unknown modification {}.", s)),
+        }
+    }
+}
+
+fn parse_strategy(value: &str) -> Result<DenomStrategy, String> {
+    value.parse()
+}
+
+#[derive(Debug, Serialize)]
+pub struct BranchSummary {
+    pub block_height: u64,
+    pub total_supply: Amount,
+    pub stakers: usize,
+    pub stake_count: u64,
+    pub orphaned_count: u64,
+}
+
+impl BranchSummary {
+    fn new(network: &Network) -> Self {
+        Self {
+            block_height: network.block_height(),
+            total_supply: network.total_supply(),
+            stakers: network.stakers().len(),
+            stake_count: network.stakers().iter().map(|p| p.stake_count()).sum(),
+            orphaned_count: network.stakers().iter().map(|p| p.orphaned_count()).sum(),
+        }
+    }
+}
+
+/// How one staker ended up in each branch. Stakers that only exist in the
+/// modified branch have no original values.
+#[derive(Debug, Serialize)]
+pub struct StakerDelta {
+    pub id: u64,
+    pub original_balance: Option<Amount>,
+    pub modified_balance: Amount,
+    pub original_pct_total: Option<f64>,
+    pub modified_pct_total: f64,
+}
+
+impl StakerDelta {
+    /// Change in share of total supply, in percentage points.
+    pub fn pct_total_diff(&self) -> f64 {
+        (self.modified_pct_total - self.original_pct_total.unwrap_or(0.0)) * 100.0
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForkComparison {
+    pub fork_height: u64,
+    pub modification: Modification,
+    pub original: BranchSummary,
+    pub modified: BranchSummary,
+    pub stakers: Vec<StakerDelta>,
+}
+
+/// Runs the checkpointed network for `blocks` blocks twice, once as is and
+/// once with `modification` applied, feeding both branches the same random
+/// stream.
+pub fn run_fork(
+    checkpoint: &Checkpoint,
+    modification: Modification,
+    blocks: u64,
+) -> Result<ForkComparison, SimError> {
+    let fork_height = checkpoint.network.block_height();
+
+    let mut original = checkpoint.network.clone();
+    let mut modified = checkpoint.network.clone();
+    modification.apply(&mut modified)?;
+
+    let mut rng = checkpoint.rng.clone();
+    for _ in 0..blocks {
+        original.step(&mut rng)?;
+    }
+    let mut rng = checkpoint.rng.clone();
+    for _ in 0..blocks {
+        modified.step(&mut rng)?;
+    }
+
+    original.update_stakers();
+    modified.update_stakers();
+
+    let stakers = modified
+        .stakers()
+        .iter()
+        .map(|staker| {
+            let before = original.stakers().iter().find(|p| p.id() == staker.id());
+            StakerDelta {
+                id: staker.id(),
+                original_balance: before.map(|p| p.balance_spendable() + p.balance_immature()),
+                modified_balance: staker.balance_spendable() + staker.balance_immature(),
+                original_pct_total: before.map(|p| p.percent_total()),
+                modified_pct_total: staker.percent_total(),
+            }
+        })
+        .collect();
+
+    Ok(ForkComparison {
+        fork_height,
+        modification,
+        original: BranchSummary::new(&original),
+        modified: BranchSummary::new(&modified),
+        stakers,
+    })
+}
+
+impl fmt::Display for ForkComparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Fork at block {}: {:?}",
+            self.fork_height, self.modification
+        )?;
+        writeln!(
+            f,
+            "{:<10} {:>12} {:>20} {:>8} {:>10} {:>10}",
+            "branch", "height", "supply", "stakers", "stakes", "orphans"
+        )?;
+        for (name, branch) in &[("original", &self.original), ("modified", &self.modified)] {
+            writeln!(
+                f,
+                "{:<10} {:>12} {:>20.2} {:>8} {:>10} {:>10}",
+                name,
+                branch.block_height,
+                branch.total_supply.to_coins(),
+                branch.stakers,
+                branch.stake_count,
+                branch.orphaned_count
+            )?;
+        }
+
+        let mut deltas: Vec<&StakerDelta> = self.stakers.iter().collect();
+        deltas.sort_by(|a, b| {
+            b.pct_total_diff()
+                .abs()
+                .partial_cmp(&a.pct_total_diff().abs())
+                .unwrap()
+        });
+        writeln!(f, "\nLargest changes in share of supply:")?;
+        writeln!(
+            f,
+            "{:>8} {:>14} {:>14} {:>12}",
+            "staker", "original %", "modified %", "diff pp"
+        )?;
+        for delta in deltas.iter().take(10) {
+            writeln!(
+                f,
+                "{:>8} {:>14.6} {:>14.6} {:>12.6}",
+                delta.id,
+                delta.original_pct_total.unwrap_or(0.0) * 100.0,
+                delta.modified_pct_total * 100.0,
+                delta.pct_total_diff()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::SimRng;
+    use rand::SeedableRng;
+
+    fn checkpoint() -> Checkpoint {
+        let mut rng = SimRng::seed_from_u64(21);
+        let mut network = Network::new();
+        network.create_stakers(&mut rng).unwrap();
+        let start = network.block_height();
+        Checkpoint::new(network, rng, start, start)
+    }
+
+    #[test]
+    fn test_parse_modifications() {
+        assert_eq!(
+            "strategy:3:onlyhuge".parse::<Modification>().unwrap(),
+            Modification::DenomStrategy {
+                staker: 3,
+                strategy: DenomStrategy::OnlyHuge
+            }
+        );
+        assert_eq!(
+            "bracket-mod:0.05".parse::<Modification>().unwrap(),
+            Modification::DenomBracketMod(0.05)
+        );
+        assert!("weight:capped:1_000".parse::<Modification>().is_ok());
+        assert!("weight:quadratic".parse::<Modification>().is_err());
+        assert!(
+            "whale:1_000_000:optimal:2.5"
+                .parse::<Modification>()
+                .is_ok()
+        );
+        assert!("reward:50:5:100000:5".parse::<Modification>().is_ok());
+        assert!("nonsense".parse::<Modification>().is_err());
+    }
+
+    #[test]
+    fn test_identity_fork_matches() {
+        let checkpoint = checkpoint();
+        let schedule = *checkpoint.network.reward_schedule();
+        let comparison =
+            run_fork(&checkpoint, Modification::RewardSchedule(schedule), 100).unwrap();
+
+        assert_eq!(
+            comparison.original.total_supply,
+            comparison.modified.total_supply
+        );
+        assert!(comparison.stakers.iter().all(|p| p.pct_total_diff() == 0.0));
+    }
+
+    #[test]
+    fn test_whale_fork() {
+        let checkpoint = checkpoint();
+        let modification = Modification::InjectWhale {
+            balance: Amount::from_coins(1_000_000),
+            strategy: DenomStrategy::Optimal,
+            computer_strength: 0.0,
+        };
+        let comparison = run_fork(&checkpoint, modification, 100).unwrap();
+
+        assert_eq!(comparison.modified.stakers, comparison.original.stakers + 1);
+        let whale = comparison.stakers.last().unwrap();
+        assert!(whale.original_balance.is_none());
+        assert!(whale.modified_balance >= Amount::from_coins(1_000_000));
+    }
+    #[test]
+    fn test_bracket_mod_needs_bracket_rule() {
+        let mut network = Network::new();
+        let linear: WeightRule = "linear".parse().unwrap();
+        network.set_weight_rule(linear);
+        assert!(matches!(
+            Modification::DenomBracketMod(0.5).apply(&mut network),
+            Err(SimError::InvalidConfig(_))
+        ));
+        assert_eq!(network.weight_rule(), linear);
+    }
+}
@@ -0,0 +1,273 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::amount::{self, Amount};
+use crate::error::SimError;
+use crate::network::Network;
+use std::fmt;
+
+/// A conservation or bookkeeping rule the network broke.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// Coins held by wallets, the float, the mempool and pools don't add up
+    /// to the total supply.
+    Supply { total_supply: Amount, held: Amount },
+    /// An amount outside `0..=MAX_MONEY`, in sats.
+    OutOfRange { what: String, sats: u64 },
+    /// A wallet's outputs disagree with its counters.
+    Wallet { staker: u64, reason: String },
+    /// The stake ranges picked from overlap or cover more than everything.
+    WeightIndex { staker: u64 },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::Supply { total_supply, held } => {
+                write!(f, "total supply is {} but {} is held", total_supply, held)
+            }
+            Violation::OutOfRange { what, sats } => {
+                write!(f, "{} of {} sats is out of range", what, sats)
+            }
+            Violation::Wallet { staker, reason } => {
+                write!(f, "wallet of staker {}: {}", staker, reason)
+            }
+            Violation::WeightIndex { staker } => {
+                write!(
+                    f,
+                    "stake range of staker {} overlaps the one before",
+                    staker
+                )
+            }
+        }
+    }
+}
+
+/// Checks the network every `interval` blocks and remembers the last height
+/// everything held at, to narrow down the block that broke it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvariantChecker {
+    pub interval: u64,
+    last_held: Option<u64>,
+}
+
+impl InvariantChecker {
+    pub fn new(interval: u64) -> Self {
+        Self {
+            interval,
+            last_held: None,
+        }
+    }
+
+    /// Height of the last check that passed.
+    pub fn last_held(&self) -> Option<u64> {
+        self.last_held
+    }
+
+    /// Checks the network if its height is a multiple of the interval. An
+    /// interval of 0 never checks.
+    pub fn step(&mut self, network: &Network) -> Result<(), SimError> {
+        let block_height = network.block_height();
+        if self.interval == 0 || !block_height.is_multiple_of(self.interval) {
+            return Ok(());
+        }
+
+        match check(network) {
+            Ok(()) => {
+                self.last_held = Some(block_height);
+                Ok(())
+            }
+            Err(violation) => Err(SimError::Invariant {
+                block_height,
+                last_held: self.last_held,
+                violation,
+            }),
+        }
+    }
+}
+
+/// Checks every invariant and returns the first one broken.
+pub fn check(network: &Network) -> Result<(), Violation> {
+    check_amounts(network)?;
+    check_supply(network)?;
+    for staker in network.stakers() {
+        staker
+            .denoms()
+            .check()
+            .map_err(|reason| Violation::Wallet {
+                staker: staker.id(),
+                reason,
+            })?;
+    }
+    check_weight_index(network)
+}
+
+fn check_range(what: &str, value: Amount) -> Result<(), Violation> {
+    if amount::money_range(value.to_sat()) {
+        Ok(())
+    } else {
+        Err(Violation::OutOfRange {
+            what: what.to_owned(),
+            sats: value.to_sat(),
+        })
+    }
+}
+
+fn check_amounts(network: &Network) -> Result<(), Violation> {
+    check_range("total supply", network.total_supply())?;
+    check_range("float", network.float())?;
+    check_range("pending fees", network.pending_fees())?;
+    for pool in network.pools() {
+        check_range(&format!("fees of pool {}", pool.id), pool.fees())?;
+    }
+    for staker in network.stakers() {
+        let wallet = staker.denoms();
+        for denom in wallet.mature().iter().chain(wallet.immature()) {
+            let what = format!("output of staker {}", staker.id());
+            check_range(&what, denom.value())?;
+        }
+        let what = format!("balance of staker {}", staker.id());
+        check_range(&what, wallet.balance())?;
+    }
+    Ok(())
+}
+
+/// Every coin minted is in a wallet, the float, the mempool or a pool's fees.
+fn check_supply(network: &Network) -> Result<(), Violation> {
+    let held = network
+        .stakers()
+        .iter()
+        .map(|p| p.denoms().balance() + p.denoms().unpaid_fees())
+        .chain(network.pools().iter().map(|p| p.fees()))
+        .fold(
+            network.float() + network.pending_fees(),
+            Amount::saturating_add,
+        );
+    if held == network.total_supply() {
+        Ok(())
+    } else {
+        Err(Violation::Supply {
+            total_supply: network.total_supply(),
+            held,
+        })
+    }
+}
+
+/// The stake ranges are laid out back to back from 0, so each one starts
+/// where the last non-empty one ended and none reaches past 1.
+fn check_weight_index(network: &Network) -> Result<(), Violation> {
+    const EPSILON: f64 = 1e-9;
+    let mut end = 0.0;
+    for staker in network.stakers() {
+        let range = staker.range();
+        if range.start >= range.end {
+            continue;
+        }
+        if range.start < end - EPSILON || range.end > 1.0 + EPSILON {
+            return Err(Violation::WeightIndex {
+                staker: staker.id(),
+            });
+        }
+        end = range.end;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adversary::AdversarySpec;
+    use crate::maturity::Maturity;
+    use crate::network::SimRng;
+    use crate::pool::Pool;
+    use crate::transaction::TransactionModel;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_invariants_hold_while_staking() {
+        let mut rng = SimRng::seed_from_u64(41);
+        let mut network = Network::new();
+        network.set_non_staking_fraction(0.1);
+        network.set_propagation(Some("4:60".parse().unwrap()));
+        network.create_stakers(&mut rng).unwrap();
+        network.set_transactions(Some(TransactionModel {
+            rate: 2.0,
+            max_fraction: 0.2,
+            external: 0.2,
+        }));
+        network.set_fees(Some("default".parse().unwrap()));
+        network.add_pool("3:20000:0.1:100000".parse::<Pool>().unwrap());
+        "selfish:1000000:0.5"
+            .parse::<AdversarySpec>()
+            .unwrap()
+            .apply(&mut network)
+            .unwrap();
+
+        let mut checker = InvariantChecker::new(1);
+        for _ in 0..400 {
+            network.step(&mut rng).unwrap();
+            checker.step(&network).unwrap();
+        }
+        assert!(network.orphaned_blocks() > 0);
+        assert_eq!(checker.last_held(), Some(network.block_height()));
+    }
+
+    #[test]
+    fn test_reports_last_height_that_held() {
+        let mut rng = SimRng::seed_from_u64(43);
+        let mut network = Network::new();
+        network.create_stakers(&mut rng).unwrap();
+        let mut checker = InvariantChecker::new(10);
+        while checker.last_held().is_none() {
+            network.step(&mut rng).unwrap();
+            checker.step(&network).unwrap();
+        }
+        let last_held = checker.last_held();
+
+        // Coins out of nowhere.
+        let block_height = network.block_height();
+        network
+            .staker_mut(0)
+            .unwrap()
+            .receive(Amount::COIN, block_height, &Maturity::default())
+            .unwrap();
+        let error = loop {
+            network.step(&mut rng).unwrap();
+            if let Err(e) = checker.step(&network) {
+                break e;
+            }
+        };
+        match error {
+            SimError::Invariant {
+                block_height,
+                last_held: held,
+                violation: Violation::Supply { .. },
+            } => {
+                assert_eq!(held, last_held);
+                assert_eq!(block_height, last_held.unwrap() + 10);
+            }
+            e => panic!("Unexpected error {}.", e),
+        }
+    }
+}
@@ -22,9 +22,29 @@
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
 
-mod amount;
-mod denom;
-mod error;
-mod network;
-mod staker;
-mod wallet;
+pub mod adversary;
+pub mod amount;
+pub mod attack;
+pub mod availability;
+pub mod capacity;
+pub mod checkpoint;
+pub mod consensus;
+pub mod denom;
+pub mod error;
+pub mod exchange;
+pub mod fee;
+pub mod fork;
+pub mod invariant;
+pub mod maturity;
+pub mod migration;
+pub mod network;
+pub mod parse;
+pub mod pool;
+pub mod population;
+pub mod propagation;
+pub mod report;
+pub mod staker;
+pub mod transaction;
+pub mod wallet;
+pub mod weight;
+pub mod zerocoin;
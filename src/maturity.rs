@@ -0,0 +1,124 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+use crate::parse::number;
+use crate::wallet::{INPUT_LOCK, STAKE_MATURITY, TRANSFER_MATURITY};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// How many blocks new outputs wait before they can stake again.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Maturity {
+    /// Stake rewards.
+    pub stake: u64,
+    /// The input that found the kernel.
+    pub input_lock: u64,
+    /// Received coins and change.
+    pub transfer: u64,
+}
+
+impl Default for Maturity {
+    fn default() -> Self {
+        Self {
+            stake: STAKE_MATURITY,
+            input_lock: INPUT_LOCK,
+            transfer: TRANSFER_MATURITY,
+        }
+    }
+}
+
+/// Maturity depths that take effect from `height` on, like a soft fork.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct MaturityPhase {
+    pub height: u64,
+    pub maturity: Maturity,
+}
+
+/// Parses `HEIGHT:STAKE:INPUT_LOCK:TRANSFER`.
+impl FromStr for MaturityPhase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<u64> = s.split(':').map(number).collect::<Result<_, _>>()?;
+        match parts.as_slice() {
+            [height, stake, input_lock, transfer] => Ok(Self {
+                height: *height,
+                maturity: Maturity {
+                    stake: *stake,
+                    input_lock: *input_lock,
+                    transfer: *transfer,
+                },
+            }),
+            _ => Err(format!(
+                "Expected HEIGHT:STAKE:INPUT_LOCK:TRANSFER, got {}.",
+                s
+            )),
+        }
+    }
+}
+
+/// The default maturity depths, replaced by each phase from its height on.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct MaturitySchedule {
+    phases: Vec<MaturityPhase>,
+}
+
+impl MaturitySchedule {
+    pub fn new(mut phases: Vec<MaturityPhase>) -> Self {
+        phases.sort_by_key(|p| p.height);
+        Self { phases }
+    }
+
+    /// Adds a phase, replacing any that starts at the same height.
+    pub fn push(&mut self, phase: MaturityPhase) {
+        self.phases.retain(|p| p.height != phase.height);
+        self.phases.push(phase);
+        self.phases.sort_by_key(|p| p.height);
+    }
+
+    pub fn at(&self, block_height: u64) -> Maturity {
+        self.phases
+            .iter()
+            .rev()
+            .find(|p| p.height <= block_height)
+            .map(|p| p.maturity)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_switches_at_height() {
+        let schedule = MaturitySchedule::new(vec![
+            "2_000:10:500:10".parse().unwrap(),
+            "1_000:60:2000:100".parse().unwrap(),
+        ]);
+        assert_eq!(schedule.at(999), Maturity::default());
+        assert_eq!(schedule.at(1_000).input_lock, 2_000);
+        assert_eq!(schedule.at(5_000).stake, 10);
+        assert!("1:2:3".parse::<MaturityPhase>().is_err());
+    }
+}
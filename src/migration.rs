@@ -0,0 +1,338 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::amount::Amount;
+use crate::parse::number;
+use crate::staker::Staker;
+use rand::Rng;
+use rand_distr::{Distribution, Exp};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// How stakers move their zerocoins to RingCT once Zerocoin staking ends.
+/// Each waits an exponentially distributed delay with mean `delay` blocks,
+/// except a `never` share that doesn't migrate at all. Migrating spends every
+/// zerocoin for `cost` per zerocoin, and the RingCT output waits
+/// `Maturity::transfer` blocks before it stakes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct MigrationModel {
+    pub delay: f64,
+    pub never: f64,
+    pub cost: Amount,
+}
+
+/// Parses `DELAY:NEVER:COST`, the cost in coins per zerocoin.
+impl FromStr for MigrationModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts.as_slice() {
+            [delay, never, cost] => {
+                let delay: f64 = number(delay)?;
+                let never: f64 = number(never)?;
+                if delay < 0.0 || !(0.0..=1.0).contains(&never) {
+                    return Err(format!(
+                        "Expected DELAY of at least 0 and NEVER in [0, 1], got {}.",
+                        s
+                    ));
+                }
+                Ok(Self {
+                    delay,
+                    never,
+                    cost: number(cost)?,
+                })
+            }
+            _ => Err(format!("Expected DELAY:NEVER:COST, got {}.", s)),
+        }
+    }
+}
+
+/// Delays, relative to the mean, that the report groups stakers by.
+static DELAY_BUCKETS: [f64; 4] = [0.5, 1.0, 2.0, f64::INFINITY];
+
+/// Where a staker stood when Zerocoin staking ended.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+struct Mark {
+    balance: Amount,
+    rewards: Amount,
+    /// Height the staker migrates at, `None` if it never does.
+    due: Option<u64>,
+    migrated: bool,
+}
+
+/// Blocks found over one stretch of the migration window.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct MigrationWindow {
+    pub start_height: u64,
+    pub blocks: u64,
+    /// Blocks found by stakers that had migrated, or joined after the switch.
+    pub migrated_blocks: u64,
+    /// Share of the coins held at the switch that had migrated by the end of
+    /// the window.
+    pub migrated_share: f64,
+}
+
+/// A migration in progress: who migrates when, and how blocks were split
+/// between migrated and waiting stakers meanwhile.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Migration {
+    model: MigrationModel,
+    start_height: u64,
+    window: u64,
+    marks: Vec<Mark>,
+    balance: Amount,
+    migrated_balance: Amount,
+    windows: Vec<MigrationWindow>,
+}
+
+impl Migration {
+    /// Draws every active staker's migration height from `block_height` on.
+    pub fn schedule<R: Rng + ?Sized>(
+        model: MigrationModel,
+        stakers: &[Staker],
+        block_height: u64,
+        rng: &mut R,
+    ) -> Self {
+        let delay = Exp::new(1.0 / model.delay.max(1.0)).unwrap();
+        let marks: Vec<Mark> = stakers
+            .iter()
+            .map(|staker| Mark {
+                balance: if staker.is_active() {
+                    staker.denoms().balance()
+                } else {
+                    Amount::ZERO
+                },
+                rewards: staker.rewards_earned(),
+                due: if staker.is_active() && rng.gen::<f64>() >= model.never {
+                    Some(block_height + delay.sample(rng) as u64)
+                } else {
+                    None
+                },
+                migrated: false,
+            })
+            .collect();
+        Self {
+            model,
+            start_height: block_height,
+            window: ((model.delay / 4.0) as u64).max(1),
+            balance: marks.iter().map(|p| p.balance).sum(),
+            marks,
+            migrated_balance: Amount::ZERO,
+            windows: Vec::new(),
+        }
+    }
+
+    pub fn model(&self) -> &MigrationModel {
+        &self.model
+    }
+
+    /// Whether the staker at `pos` is due to migrate at `block_height`.
+    pub fn is_due(&self, pos: usize, block_height: u64) -> bool {
+        self.marks
+            .get(pos)
+            .is_some_and(|p| !p.migrated && p.due.is_some_and(|due| due <= block_height))
+    }
+
+    pub fn set_migrated(&mut self, pos: usize) {
+        let mark = &mut self.marks[pos];
+        mark.migrated = true;
+        self.migrated_balance += mark.balance;
+    }
+
+    /// Counts the block found at `block_height`, if any, against its window.
+    pub fn record_block(&mut self, producer: Option<usize>, block_height: u64) {
+        let index = ((block_height - self.start_height) / self.window) as usize;
+        while self.windows.len() <= index {
+            self.windows.push(MigrationWindow {
+                start_height: self.start_height + self.windows.len() as u64 * self.window,
+                blocks: 0,
+                migrated_blocks: 0,
+                migrated_share: 0.0,
+            });
+        }
+
+        let migrated_share = if self.balance.is_zero() {
+            1.0
+        } else {
+            self.migrated_balance.to_sat() as f64 / self.balance.to_sat() as f64
+        };
+        let window = &mut self.windows[index];
+        window.migrated_share = migrated_share;
+        if let Some(pos) = producer {
+            window.blocks += 1;
+            if self.marks.get(pos).is_none_or(|p| p.migrated) {
+                window.migrated_blocks += 1;
+            }
+        }
+    }
+
+    pub fn report(&self, stakers: &[Staker]) -> MigrationReport {
+        let mut rows: Vec<MigrationRow> = DELAY_BUCKETS
+            .iter()
+            .map(|p| MigrationRow {
+                max_delay: Some(*p),
+                stakers: 0,
+                balance: Amount::ZERO,
+                rewards: Amount::ZERO,
+            })
+            .collect();
+        rows.push(MigrationRow {
+            max_delay: None,
+            stakers: 0,
+            balance: Amount::ZERO,
+            rewards: Amount::ZERO,
+        });
+
+        let mean = self.model.delay.max(1.0);
+        for (mark, staker) in self.marks.iter().zip(stakers) {
+            if mark.balance.is_zero() {
+                continue;
+            }
+            let row = match mark.due {
+                Some(due) => {
+                    let delay = (due - self.start_height) as f64 / mean;
+                    let bucket = DELAY_BUCKETS.iter().position(|p| delay < *p).unwrap();
+                    &mut rows[bucket]
+                }
+                None => rows.last_mut().unwrap(),
+            };
+            row.stakers += 1;
+            row.balance += mark.balance;
+            row.rewards += staker.rewards_earned().saturating_sub(mark.rewards);
+        }
+
+        // Stop listing windows once everyone who migrates has.
+        let last = self
+            .marks
+            .iter()
+            .filter_map(|p| p.due)
+            .max()
+            .unwrap_or(self.start_height);
+        let windows = self
+            .windows
+            .iter()
+            .take_while(|p| p.start_height <= last)
+            .copied()
+            .collect();
+        MigrationReport {
+            start_height: self.start_height,
+            rows,
+            windows,
+        }
+    }
+}
+
+/// Stakers grouped by how long they took to migrate, relative to the mean
+/// delay. `max_delay` is `None` for stakers that never did.
+#[derive(Debug, Serialize)]
+pub struct MigrationRow {
+    pub max_delay: Option<f64>,
+    pub stakers: usize,
+    pub balance: Amount,
+    pub rewards: Amount,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationReport {
+    pub start_height: u64,
+    pub rows: Vec<MigrationRow>,
+    pub windows: Vec<MigrationWindow>,
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let balance: Amount = self.rows.iter().map(|p| p.balance).sum();
+        let rewards: Amount = self.rows.iter().map(|p| p.rewards).sum();
+        writeln!(f, "Zerocoin staking ended at {}.", self.start_height)?;
+        writeln!(
+            f,
+            "{:>14} {:>8} {:>9} {:>9} {:>9}",
+            "migrated", "stakers", "balance%", "rewards%", "return%"
+        )?;
+        let mut min_delay = 0.0;
+        for row in &self.rows {
+            let label = match row.max_delay {
+                Some(max) if max.is_finite() => format!("{}-{}x delay", min_delay, max),
+                Some(_) => format!(">{}x delay", min_delay),
+                None => "never".to_owned(),
+            };
+            if let Some(max) = row.max_delay {
+                min_delay = max;
+            }
+            writeln!(
+                f,
+                "{:>14} {:>8} {:>9.3} {:>9.3} {:>9.4}",
+                label,
+                row.stakers,
+                share(row.balance.to_sat(), balance.to_sat()) * 100.0,
+                share(row.rewards.to_sat(), rewards.to_sat()) * 100.0,
+                share(row.rewards.to_sat(), row.balance.to_sat()) * 100.0
+            )?;
+        }
+
+        writeln!(
+            f,
+            "{:>12} {:>8} {:>10} {:>18}",
+            "from", "blocks", "migrated%", "migrated blocks%"
+        )?;
+        for window in &self.windows {
+            writeln!(
+                f,
+                "{:>12} {:>8} {:>10.3} {:>18.3}",
+                window.start_height,
+                window.blocks,
+                window.migrated_share * 100.0,
+                share(window.migrated_blocks, window.blocks) * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn share(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_model() {
+        let model: MigrationModel = "1000:0.1:0.01".parse().unwrap();
+        assert_eq!(model.cost, Amount::COIN / 100);
+        assert!("1000:1.5:0.01".parse::<MigrationModel>().is_err());
+        assert!("1000:0.1".parse::<MigrationModel>().is_err());
+        assert!("1000:0.1:-1".parse::<MigrationModel>().is_err());
+        assert!("1000:0.1:300000001".parse::<MigrationModel>().is_err());
+        let model: MigrationModel = "1000:0.1:1_000_000sat".parse().unwrap();
+        assert_eq!(model.cost, Amount::COIN / 100);
+    }
+}
@@ -0,0 +1,1659 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::adversary::Behavior;
+use crate::amount::Amount;
+use crate::availability::AvailabilityModel;
+use crate::capacity::CapacityModel;
+use crate::consensus::{ConsensusChange, ConsensusReport, ConsensusSchedule, PeriodTracker};
+use crate::denom::DenomStrategy;
+use crate::error::SimError;
+use crate::exchange::{EXCHANGE_STRENGTH, Exchange};
+use crate::fee::{EraIncome, FeeModel, FeeReport};
+use crate::maturity::{MaturityPhase, MaturitySchedule};
+use crate::migration::{Migration, MigrationModel, MigrationReport};
+use crate::pool::Pool;
+use crate::population::PopulationSchedule;
+use crate::propagation::PropagationModel;
+use crate::staker::Staker;
+use crate::transaction::TransactionModel;
+use crate::wallet::Sent;
+use crate::weight::{StakeMods, WeightRule};
+use crate::zerocoin::{ZerocoinModel, ZerocoinReport};
+use rand::Rng;
+use rand_distr::{Distribution, LogNormal, Poisson};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// RNG driving a simulation. Its state is serializable so checkpointed runs
+/// resume on the exact same random stream.
+pub type SimRng = rand_pcg::Pcg64Mcg;
+
+pub static STAKE_REWARD: Amount = Amount::from_coins(50);
+// static MAX_SUPPLY: u64 = 300_000_000;
+pub static SUPER_BLOCK: u64 = 43_200;
+pub static REWARD_REDUCTION_BLOCK: u64 = 525_960;
+/// Blocks between samples of each staker's immature balance.
+pub static IMMATURE_SAMPLE_INTERVAL: u64 = 100;
+
+/// Block subsidy over time. The reward drops by `reduction` every `interval`
+/// blocks until it reaches `floor`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct RewardSchedule {
+    pub initial: Amount,
+    pub reduction: Amount,
+    pub interval: u64,
+    pub floor: Amount,
+}
+
+impl Default for RewardSchedule {
+    fn default() -> Self {
+        Self {
+            initial: STAKE_REWARD,
+            reduction: Amount::from_coins(10),
+            interval: REWARD_REDUCTION_BLOCK,
+            floor: Amount::from_coins(10),
+        }
+    }
+}
+
+impl RewardSchedule {
+    /// Block subsidy at the given height.
+    pub fn reward(&self, block_height: u64) -> Amount {
+        let era = block_height / self.interval.max(1);
+        let reduction = self.reduction.checked_mul(era).unwrap_or(Amount::MAX_MONEY);
+        self.initial
+            .saturating_sub(reduction)
+            .max(self.floor.min(self.initial))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Network {
+    stakers: Vec<Staker>,
+    total_supply: Amount,
+    block_height: u64,
+    reward_schedule: RewardSchedule,
+    weight_rule: WeightRule,
+    stake_mods: StakeMods,
+    zerocoin: Option<ZerocoinModel>,
+    migration_model: Option<MigrationModel>,
+    migration: Option<Migration>,
+    consensus: ConsensusSchedule,
+    periods: Option<PeriodTracker>,
+    float: Amount,
+    non_staking_fraction: f64,
+    population: PopulationSchedule,
+    availability: Option<AvailabilityModel>,
+    capacity: Option<CapacityModel>,
+    propagation: Option<PropagationModel>,
+    pools: Vec<Pool>,
+    exchanges: Vec<Exchange>,
+    transactions: Option<TransactionModel>,
+    fees: Option<FeeModel>,
+    /// Fees paid by transactions not yet in a block.
+    pending_fees: Amount,
+    era_income: Vec<EraIncome>,
+    maturity: MaturitySchedule,
+    orphaned_blocks: u64,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Network {
+    pub fn new() -> Self {
+        Self {
+            stakers: Vec::new(),
+            // Start 6 months, gets more stakers.
+            total_supply: STAKE_REWARD * SUPER_BLOCK * 6,
+            block_height: SUPER_BLOCK * 6,
+            reward_schedule: RewardSchedule::default(),
+            weight_rule: WeightRule::default(),
+            stake_mods: StakeMods::default(),
+            zerocoin: None,
+            migration_model: None,
+            migration: None,
+            consensus: ConsensusSchedule::default(),
+            periods: None,
+            float: Amount::ZERO,
+            non_staking_fraction: 0.0,
+            population: PopulationSchedule::default(),
+            availability: None,
+            capacity: None,
+            propagation: None,
+            pools: Vec::new(),
+            exchanges: Vec::new(),
+            transactions: None,
+            fees: None,
+            pending_fees: Amount::ZERO,
+            era_income: Vec::new(),
+            maturity: MaturitySchedule::default(),
+            orphaned_blocks: 0,
+        }
+    }
+
+    pub fn stakers(&self) -> &[Staker] {
+        &self.stakers
+    }
+
+    pub fn total_supply(&self) -> Amount {
+        self.total_supply
+    }
+
+    pub fn block_height(&self) -> u64 {
+        self.block_height
+    }
+
+    pub fn reward_schedule(&self) -> &RewardSchedule {
+        &self.reward_schedule
+    }
+
+    pub fn set_reward_schedule(&mut self, reward_schedule: RewardSchedule) {
+        self.reward_schedule = reward_schedule;
+    }
+
+    pub fn weight_rule(&self) -> WeightRule {
+        self.weight_rule
+    }
+
+    /// Switches every wallet, and every staker that joins later, to
+    /// `weight_rule`.
+    pub fn set_weight_rule(&mut self, weight_rule: WeightRule) {
+        self.weight_rule = weight_rule;
+        self.stakers
+            .iter_mut()
+            .for_each(|p| p.set_weight_rule(weight_rule));
+    }
+
+    pub fn stake_mods(&self) -> &StakeMods {
+        &self.stake_mods
+    }
+
+    /// Applies the stake modifier rules to every wallet, and every staker
+    /// that joins later.
+    pub fn set_stake_mods(&mut self, stake_mods: StakeMods) {
+        for staker in &mut self.stakers {
+            staker.set_stake_mods(stake_mods.clone());
+        }
+        self.stake_mods = stake_mods;
+    }
+
+    pub fn zerocoin(&self) -> Option<ZerocoinModel> {
+        self.zerocoin
+    }
+
+    /// Switches Zerocoin staking on or off for every wallet, and every staker
+    /// that joins later. Turned on, mature holdings count as already minted.
+    /// Turned off, everyone migrates their zerocoins to RingCT at once and for
+    /// free, see `end_zerocoin` for a gradual migration.
+    pub fn set_zerocoin(&mut self, zerocoin: Option<ZerocoinModel>) -> Result<(), SimError> {
+        self.zerocoin = zerocoin;
+        let block_height = self.block_height;
+        for staker in &mut self.stakers {
+            staker.set_zerocoin(zerocoin, block_height)?;
+            if zerocoin.is_none() {
+                staker.migrate(block_height, Amount::ZERO, block_height)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn migration_model(&self) -> Option<&MigrationModel> {
+        self.migration_model.as_ref()
+    }
+
+    /// How stakers migrate to RingCT when Zerocoin staking ends.
+    pub fn set_migration_model(&mut self, migration_model: Option<MigrationModel>) {
+        self.migration_model = migration_model;
+    }
+
+    /// Ends Zerocoin staking. Under a migration model every staker's
+    /// zerocoins stop staking and wait for its migration height, otherwise
+    /// they are migrated right away.
+    pub fn end_zerocoin<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<(), SimError> {
+        let model = match self.migration_model {
+            Some(model) if self.zerocoin.is_some() => model,
+            _ => return self.set_zerocoin(None),
+        };
+        self.zerocoin = None;
+        let block_height = self.block_height;
+        for staker in &mut self.stakers {
+            staker.set_zerocoin(None, block_height)?;
+        }
+        self.migration = Some(Migration::schedule(model, &self.stakers, block_height, rng));
+        Ok(())
+    }
+
+    pub fn migration_report(&self) -> Option<MigrationReport> {
+        self.migration.as_ref().map(|p| p.report(&self.stakers))
+    }
+
+    pub fn zerocoin_report(&self) -> ZerocoinReport {
+        ZerocoinReport::from_stakers(&self.stakers)
+    }
+
+    pub fn consensus(&self) -> &ConsensusSchedule {
+        &self.consensus
+    }
+
+    /// Schedules hard forks. Results are tracked from here on and split at
+    /// every activation height.
+    pub fn set_consensus(&mut self, consensus: ConsensusSchedule) {
+        self.periods = if consensus.is_empty() {
+            None
+        } else {
+            Some(PeriodTracker::new(&self.stakers, self.block_height))
+        };
+        self.consensus = consensus;
+    }
+
+    pub fn consensus_report(&self) -> Option<ConsensusReport> {
+        self.periods
+            .as_ref()
+            .map(|p| p.report(&self.stakers, self.block_height))
+    }
+
+    pub fn apply_consensus_change<R: Rng + ?Sized>(
+        &mut self,
+        change: &ConsensusChange,
+        rng: &mut R,
+    ) -> Result<(), SimError> {
+        match change {
+            ConsensusChange::Weight(weight_rule) => self.set_weight_rule(*weight_rule),
+            ConsensusChange::StakeMods(stake_mods) => self.set_stake_mods(stake_mods.clone()),
+            ConsensusChange::Maturity(maturity) => self.maturity.push(MaturityPhase {
+                height: self.block_height,
+                maturity: *maturity,
+            }),
+            ConsensusChange::Zerocoin(Some(zerocoin)) => return self.set_zerocoin(Some(*zerocoin)),
+            ConsensusChange::Zerocoin(None) => return self.end_zerocoin(rng),
+        }
+        Ok(())
+    }
+
+    /// Changes the bracket penalty of the weight rule, see
+    /// `WeightRule::with_bracket_mod`.
+    pub fn set_denom_bracket_mod(&mut self, bracket_mod: f64) -> Result<(), SimError> {
+        self.set_weight_rule(self.weight_rule.with_bracket_mod(bracket_mod)?);
+        Ok(())
+    }
+
+    /// Coins held outside the staking set.
+    pub fn float(&self) -> Amount {
+        self.float
+    }
+
+    /// Share of the starting supply held by exchanges, lost coins and
+    /// wallets that never stake.
+    pub fn non_staking_fraction(&self) -> f64 {
+        self.non_staking_fraction
+    }
+
+    /// Must be set before `create_stakers` to take effect.
+    pub fn set_non_staking_fraction(&mut self, non_staking_fraction: f64) {
+        self.non_staking_fraction = non_staking_fraction.clamp(0.0, 1.0);
+    }
+
+    /// Share of the supply held by stakers that stake, leaving out the float
+    /// and exchanges that only hold.
+    pub fn participation_rate(&self) -> f64 {
+        let idle: Amount = self
+            .stakers
+            .iter()
+            .filter(|p| p.is_active() && !p.is_staking())
+            .map(|p| p.denoms().balance())
+            .sum();
+        1.0 - (self.float + idle).to_sat() as f64 / self.total_supply.to_sat() as f64
+    }
+
+    /// Combined stake weight of every active staker.
+    pub fn total_weight(&self) -> u64 {
+        self.stakers
+            .iter()
+            .filter(|p| p.is_staking())
+            .map(Staker::stake_weight)
+            .sum()
+    }
+
+    pub fn population(&self) -> &PopulationSchedule {
+        &self.population
+    }
+
+    pub fn set_population(&mut self, population: PopulationSchedule) {
+        self.population = population;
+    }
+
+    /// `None` keeps every node online.
+    pub fn availability(&self) -> Option<&AvailabilityModel> {
+        self.availability.as_ref()
+    }
+
+    pub fn set_availability(&mut self, availability: Option<AvailabilityModel>) {
+        self.availability = availability;
+    }
+
+    /// `None` falls back to the flat denom threshold for orphaning.
+    pub fn capacity(&self) -> Option<&CapacityModel> {
+        self.capacity.as_ref()
+    }
+
+    pub fn set_capacity(&mut self, capacity: Option<CapacityModel>) {
+        self.capacity = capacity;
+    }
+
+    /// `None` propagates blocks instantly, so blocks never compete.
+    pub fn propagation(&self) -> Option<&PropagationModel> {
+        self.propagation.as_ref()
+    }
+
+    pub fn set_propagation(&mut self, propagation: Option<PropagationModel>) {
+        self.propagation = propagation;
+    }
+
+    /// Cold staking pools, indexed by id.
+    pub fn pools(&self) -> &[Pool] {
+        &self.pools
+    }
+
+    /// Adds a pool and lets every staker small enough delegate to it, unless
+    /// it already belongs to a pool. Returns the pool id.
+    pub fn add_pool(&mut self, mut pool: Pool) -> u64 {
+        let id = self.pools.len() as u64;
+        pool.id = id;
+        self.pools.push(pool);
+        for pos in 0..self.stakers.len() {
+            if self.stakers[pos].pool().is_none() {
+                self.join_pool(pos);
+            }
+        }
+        id
+    }
+
+    /// Delegates the staker to the least crowded pool that takes its balance.
+    fn join_pool(&mut self, pos: usize) {
+        let balance = self.stakers[pos].start_balance();
+        let pool = self
+            .pools
+            .iter()
+            .filter(|p| balance <= p.max_member_balance)
+            .min_by_key(|p| {
+                self.stakers
+                    .iter()
+                    .filter(|s| s.is_active() && s.pool() == Some(p.id))
+                    .count()
+            })
+            .map(|p| p.id);
+        if pool.is_some() {
+            self.stakers[pos].set_pool(pool);
+        }
+    }
+
+    pub fn exchanges(&self) -> &[Exchange] {
+        &self.exchanges
+    }
+
+    /// Exchanges to fund when the stakers are created.
+    pub fn set_exchanges(&mut self, exchanges: Vec<Exchange>) {
+        self.exchanges = exchanges;
+    }
+
+    pub fn transactions(&self) -> Option<&TransactionModel> {
+        self.transactions.as_ref()
+    }
+
+    pub fn set_transactions(&mut self, transactions: Option<TransactionModel>) {
+        self.transactions = transactions;
+    }
+
+    pub fn maturity(&self) -> &MaturitySchedule {
+        &self.maturity
+    }
+
+    pub fn set_maturity(&mut self, maturity: MaturitySchedule) {
+        self.maturity = maturity;
+    }
+
+    pub fn fees(&self) -> Option<&FeeModel> {
+        self.fees.as_ref()
+    }
+
+    pub fn set_fees(&mut self, fees: Option<FeeModel>) {
+        self.fees = fees;
+    }
+
+    pub fn pending_fees(&self) -> Amount {
+        self.pending_fees
+    }
+
+    pub fn fee_report(&self) -> FeeReport {
+        FeeReport {
+            eras: self.era_income.clone(),
+        }
+    }
+
+    /// Takes the fee out of a transaction, if there is a fee model, and
+    /// returns what's left for the receiver.
+    fn pay_fee<R: Rng + ?Sized>(&mut self, sent: Sent, rng: &mut R) -> Amount {
+        let fee = match &self.fees {
+            Some(model) if !sent.value.is_zero() => model.fee(&sent, rng),
+            _ => Amount::ZERO,
+        };
+        self.pending_fees += fee;
+        sent.value - fee
+    }
+
+    /// Books a block's subsidy and fees under its reward era, or takes them
+    /// back if the block was orphaned. Only the subsidy adds to the supply,
+    /// fees were already part of it.
+    fn book_income(&mut self, block_height: u64, reward: Amount, orphaned: bool) {
+        let subsidy = self.reward_schedule.reward(block_height).min(reward);
+        let era = (block_height / self.reward_schedule.interval.max(1)) as usize;
+        while self.era_income.len() <= era {
+            self.era_income.push(EraIncome {
+                era: self.era_income.len() as u64,
+                ..EraIncome::default()
+            });
+        }
+
+        let income = &mut self.era_income[era];
+        if orphaned {
+            income.blocks -= 1;
+            income.subsidy -= subsidy;
+            income.fees -= reward - subsidy;
+            self.total_supply -= subsidy;
+        } else {
+            income.blocks += 1;
+            income.subsidy += subsidy;
+            income.fees += reward - subsidy;
+            self.total_supply += subsidy;
+        }
+    }
+
+    fn is_exchange(&self, staker: &Staker) -> bool {
+        self.exchanges
+            .iter()
+            .any(|p| p.staker() == Some(staker.id()))
+    }
+
+    /// Blocks that lost a fork to a competing block.
+    pub fn orphaned_blocks(&self) -> u64 {
+        self.orphaned_blocks
+    }
+
+    pub fn staker_mut(&mut self, id: u64) -> Option<&mut Staker> {
+        self.stakers.iter_mut().find(|p| p.id() == id)
+    }
+
+    /// Adds a staker holding freshly created coins, e.g. a whale showing up
+    /// in a counterfactual branch. Returns its id.
+    pub fn add_staker(
+        &mut self,
+        balance: Amount,
+        denom_strat: DenomStrategy,
+        computer_strength: f64,
+    ) -> Result<u64, SimError> {
+        let id = self.next_staker_id();
+        let total_supply = self
+            .total_supply
+            .checked_add(balance)
+            .ok_or(SimError::OutOfRange(balance.to_sat()))?;
+        let mut staker = Staker::try_with_params(
+            balance,
+            id,
+            balance.to_sat() as f64 / total_supply.to_sat() as f64,
+            denom_strat,
+            computer_strength,
+        )?;
+        self.total_supply = total_supply;
+        staker.set_weight_rule(self.weight_rule);
+        staker.set_stake_mods(self.stake_mods.clone());
+        if self.zerocoin.is_some() {
+            staker.set_zerocoin(self.zerocoin, self.block_height)?;
+        }
+        staker.set_joined_height(self.block_height);
+        self.stakers.push(staker);
+        Ok(id)
+    }
+
+    fn next_staker_id(&self) -> u64 {
+        self.stakers.iter().map(|p| p.id() + 1).max().unwrap_or(0)
+    }
+
+    fn sample_balance<R: Rng + ?Sized>(rng: &mut R) -> Amount {
+        let log_normal = LogNormal::new(0.1, 1.5).unwrap();
+        Amount::from_coins((log_normal.sample(rng) * 5_000f64) as u64).min(Amount::MAX_MONEY)
+    }
+
+    /// `balance` as a share of the total supply.
+    fn share_of_supply(&self, balance: Amount) -> f64 {
+        balance.to_sat() as f64 / self.total_supply.to_sat() as f64
+    }
+
+    pub fn create_stakers<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<(), SimError> {
+        let total_supply = self.total_supply.to_sat() as f64;
+        let non_staking = self.total_supply.fraction(self.non_staking_fraction);
+        self.float += non_staking;
+        let mut total_staking_supply = self.total_supply - non_staking;
+        let mut id = 0;
+        for exchange in &mut self.exchanges {
+            let balance = self
+                .total_supply
+                .fraction(exchange.share)
+                .min(total_staking_supply);
+            total_staking_supply -= balance;
+
+            let mut staker = Staker::try_with_params(
+                balance,
+                id,
+                balance.to_sat() as f64 / total_supply,
+                exchange.strategy,
+                EXCHANGE_STRENGTH,
+            )?;
+            staker.set_staking(exchange.staking);
+            staker.set_joined_height(self.block_height);
+            self.stakers.push(staker);
+            exchange.set_staker(id);
+            id += 1;
+        }
+        if total_staking_supply.is_zero() {
+            return Ok(());
+        }
+        loop {
+            let mut balance = Self::sample_balance(rng);
+
+            if balance >= total_staking_supply {
+                balance = total_staking_supply;
+                total_staking_supply = Amount::ZERO;
+            } else {
+                total_staking_supply -= balance;
+            }
+
+            let mut staker = Staker::try_new(balance, id, self.share_of_supply(balance), rng)?;
+            staker.set_joined_height(self.block_height);
+            self.stakers.push(staker);
+
+            if total_staking_supply.is_zero() {
+                return Ok(());
+            }
+
+            id += 1;
+        }
+    }
+
+    pub fn update_stakers(&mut self) {
+        let total_supply = self.total_supply;
+        let block_height = self.block_height;
+        self.stakers
+            .iter_mut()
+            .for_each(|p| p.update(total_supply, block_height));
+    }
+
+    /// Lets stakers join or leave according to the population schedule.
+    /// Newcomers buy their coins from the float, so nobody can join while it
+    /// is empty.
+    pub fn update_population<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<(), SimError> {
+        let (arrival_rate, departure_rate) = self.population.rates(self.block_height);
+
+        if departure_rate > 0.0 {
+            let departures = Poisson::new(departure_rate).unwrap().sample(rng);
+            for _ in 0..departures {
+                let active: Vec<usize> = (0..self.stakers.len())
+                    .filter(|i| {
+                        self.stakers[*i].is_active() && !self.is_exchange(&self.stakers[*i])
+                    })
+                    .collect();
+                if active.is_empty() {
+                    break;
+                }
+                let leaving = active[rng.gen_range(0, active.len())];
+                self.float += self.stakers[leaving].leave(self.block_height);
+            }
+        }
+
+        if arrival_rate > 0.0 {
+            let arrivals = Poisson::new(arrival_rate).unwrap().sample(rng);
+            for _ in 0..arrivals {
+                let balance = Self::sample_balance(rng).min(self.float);
+                if balance.is_zero() {
+                    break;
+                }
+                self.float -= balance;
+
+                let mut staker = Staker::try_new(
+                    balance,
+                    self.next_staker_id(),
+                    self.share_of_supply(balance),
+                    rng,
+                )?;
+                staker.set_weight_rule(self.weight_rule);
+                staker.set_stake_mods(self.stake_mods.clone());
+                if self.zerocoin.is_some() {
+                    staker.set_zerocoin(self.zerocoin, self.block_height)?;
+                }
+                staker.set_joined_height(self.block_height);
+                self.stakers.push(staker);
+                if !self.pools.is_empty() {
+                    self.join_pool(self.stakers.len() - 1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves coins between exchanges and regular stakers. Every deposit and
+    /// withdrawal goes to or from a random staker and is sized like a small
+    /// holding.
+    pub fn update_exchanges<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<(), SimError> {
+        if self.exchanges.is_empty() {
+            return Ok(());
+        }
+
+        let block_height = self.block_height;
+        let maturity = self.maturity.at(block_height);
+        let customers: Vec<usize> = (0..self.stakers.len())
+            .filter(|i| self.stakers[*i].is_active() && !self.is_exchange(&self.stakers[*i]))
+            .collect();
+        if customers.is_empty() {
+            return Ok(());
+        }
+
+        for i in 0..self.exchanges.len() {
+            let wallet = match self.exchanges[i].staker() {
+                Some(id) => id as usize,
+                None => continue,
+            };
+
+            let deposits = poisson(self.exchanges[i].deposit_rate, rng);
+            for _ in 0..deposits {
+                let customer = customers[rng.gen_range(0, customers.len())];
+                let value = Self::sample_balance(rng) / 10;
+                let sent = self.stakers[customer].send(value, block_height, &maturity)?;
+                let received = self.pay_fee(sent, rng);
+                if !received.is_zero() {
+                    self.stakers[wallet].receive(received, block_height, &maturity)?;
+                    self.exchanges[i].add_deposit(received);
+                }
+            }
+
+            let withdrawals = poisson(self.exchanges[i].withdrawal_rate, rng);
+            for _ in 0..withdrawals {
+                let customer = customers[rng.gen_range(0, customers.len())];
+                let value = Self::sample_balance(rng) / 10;
+                let sent = self.stakers[wallet].send(value, block_height, &maturity)?;
+                let received = self.pay_fee(sent, rng);
+                if !received.is_zero() {
+                    self.stakers[customer].receive(received, block_height, &maturity)?;
+                    self.exchanges[i].add_withdrawal(received);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Lets stakers pay each other, and people outside the staking set,
+    /// according to the transaction model.
+    pub fn update_transactions<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<(), SimError> {
+        let model = match self.transactions {
+            Some(model) => model,
+            None => return Ok(()),
+        };
+        let transfers = poisson(model.rate, rng);
+        if transfers == 0 {
+            return Ok(());
+        }
+
+        let block_height = self.block_height;
+        let maturity = self.maturity.at(block_height);
+        let users: Vec<usize> = (0..self.stakers.len())
+            .filter(|i| self.stakers[*i].is_active() && !self.is_exchange(&self.stakers[*i]))
+            .collect();
+        if users.len() < 2 {
+            return Ok(());
+        }
+
+        for _ in 0..transfers {
+            let sender = users[rng.gen_range(0, users.len())];
+            let external = rng.gen::<f64>() < model.external;
+            if external && rng.gen::<bool>() {
+                // Paid by someone outside, out of the float.
+                let value = (Self::sample_balance(rng) / 10).min(self.float);
+                if !value.is_zero() {
+                    self.float -= value;
+                    self.stakers[sender].receive(value, block_height, &maturity)?;
+                }
+                continue;
+            }
+
+            let balance = self.stakers[sender].denoms().mature_balance();
+            let value =
+                balance.fraction(rng.gen_range(0.0, model.max_fraction.max(f64::MIN_POSITIVE)));
+            let sent = self.stakers[sender].send(value, block_height, &maturity)?;
+            let sent = self.pay_fee(sent, rng);
+            if sent.is_zero() {
+                continue;
+            }
+            if external {
+                self.float += sent;
+            } else {
+                let mut receiver = users[rng.gen_range(0, users.len() - 1)];
+                if receiver == sender {
+                    receiver = users[users.len() - 1];
+                }
+                self.stakers[receiver].receive(sent, block_height, &maturity)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stakes the current block, then moves on to the next height.
+    pub fn step<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<(), SimError> {
+        self.activate_forks(rng)?;
+        self.update_population(rng)?;
+        self.update_exchanges(rng)?;
+        self.update_transactions(rng)?;
+        self.stake(rng)?;
+        if self.block_height.is_multiple_of(IMMATURE_SAMPLE_INTERVAL) {
+            self.stakers
+                .iter_mut()
+                .filter(|p| p.is_active())
+                .for_each(Staker::sample_immature);
+        }
+        self.block_height += 1;
+        Ok(())
+    }
+
+    /// Applies the consensus changes due at this height and starts a new
+    /// results period.
+    fn activate_forks<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<(), SimError> {
+        let due = self.consensus.activate(self.block_height);
+        if due.is_empty() {
+            return Ok(());
+        }
+        for activation in &due {
+            self.apply_consensus_change(&activation.change, rng)?;
+        }
+        if let Some(periods) = &mut self.periods {
+            let changes = due.iter().map(|p| p.change.to_string()).collect();
+            periods.fork(&self.stakers, self.block_height, changes);
+        }
+        Ok(())
+    }
+
+    pub fn stake<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<(), SimError> {
+        let block_height = self.block_height;
+        let fees = std::mem::take(&mut self.pending_fees);
+        let reward = self.reward_schedule.reward(block_height) + fees;
+        let availability = self.availability;
+        let transfer_maturity = self.maturity.at(block_height).transfer;
+        for (pos, staker) in self.stakers.iter_mut().enumerate() {
+            if !staker.is_active() {
+                continue;
+            }
+            if let Some(migration) = &mut self.migration {
+                if migration.is_due(pos, block_height) {
+                    let cost = migration.model().cost;
+                    staker.migrate(block_height, cost, block_height + transfer_maturity)?;
+                    migration.set_migrated(pos);
+                }
+            }
+            if staker.are_stakes_maturing() {
+                staker.mature_balances(block_height)?;
+            }
+            self.pending_fees += staker.take_fees();
+            staker.reweigh(block_height);
+            if let Some(model) = &availability {
+                staker.update_uptime(model, block_height, rng);
+            }
+        }
+
+        // A staker whose node is offline forfeits the slot and the block goes
+        // to whoever is online. If nobody is, the block is lost.
+        let pos = self
+            .pick_staker(false, rng)
+            .ok_or(SimError::EmptySelection {
+                selection: "staker with weight",
+                block_height,
+            })?;
+        let winner = if self.stakers[pos].is_online() {
+            Some(pos)
+        } else {
+            self.stakers[pos].miss_stake();
+            self.pick_staker(true, rng)
+        };
+
+        let capacity = self.capacity;
+        let mut producer = None;
+        if let Some(pos) = winner {
+            if self.hit_stake(pos, reward, rng)? {
+                producer = Some(pos);
+            } else if capacity.is_some() {
+                // Under the capacity model a kernel the winner couldn't
+                // hash in time is found by someone else a little later.
+                for _ in 0..self.stakers.len() {
+                    let pos = match self.pick_staker(true, rng) {
+                        Some(pos) => pos,
+                        None => break,
+                    };
+                    if self.hit_stake(pos, reward, rng)? {
+                        producer = Some(pos);
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.add_fair_rewards(reward);
+        if let Some(migration) = &mut self.migration {
+            migration.record_block(producer, block_height);
+        }
+
+        let producer = match producer {
+            Some(pos) if self.stakers[pos].behavior().is_selfish() => {
+                self.stakers[pos].withhold(block_height, reward);
+                self.book_income(block_height, reward, false);
+                return Ok(());
+            }
+            Some(pos) => pos,
+            None => {
+                // Nobody made a block, so the fees wait for the next one.
+                self.pending_fees += fees;
+                return Ok(());
+            }
+        };
+        self.book_income(block_height, reward, false);
+        if self.race_withheld(producer, reward, rng) {
+            return Ok(());
+        }
+
+        if let Some(model) = self.propagation {
+            self.resolve_fork(producer, &model, reward, rng)?;
+        }
+        Ok(())
+    }
+
+    /// Credits every adversary with its balance share of the block reward, to
+    /// compare against what it actually earned.
+    fn add_fair_rewards(&mut self, reward: Amount) {
+        if self.stakers.iter().all(|p| p.behavior().is_honest()) {
+            return;
+        }
+
+        let staked = self.total_supply - self.float;
+        if staked.is_zero() {
+            return;
+        }
+        for staker in &mut self.stakers {
+            if staker.is_active() && !staker.behavior().is_honest() {
+                let share = staker.denoms().balance().to_sat() as f64 / staked.to_sat() as f64;
+                staker.add_fair_reward(reward.to_sat() as f64 * share);
+            }
+        }
+    }
+
+    /// Lets a selfish staker sitting on withheld blocks answer the honest block
+    /// at `producer`. With a lead of one the two blocks race and the selfish
+    /// one wins with probability gamma. With a bigger lead the honest block is
+    /// always orphaned, and at two the selfish staker publishes everything.
+    /// Returns true if the honest block was orphaned.
+    fn race_withheld<R: Rng + ?Sized>(
+        &mut self,
+        producer: usize,
+        reward: Amount,
+        rng: &mut R,
+    ) -> bool {
+        let selfish = match self.stakers.iter().position(|p| !p.withheld().is_empty()) {
+            Some(pos) => pos,
+            None => return false,
+        };
+        let gamma = match self.stakers[selfish].behavior() {
+            Behavior::Selfish { gamma } => gamma,
+            _ => 0.0,
+        };
+
+        let block_height = self.block_height;
+        let lead = self.stakers[selfish].withheld().len();
+        let honest_wins = lead == 1 && rng.gen::<f64>() >= gamma;
+        // The orphaned block's transactions go back to the mempool.
+        if honest_wins {
+            let (height, withheld_reward) = self.stakers[selfish].withheld()[0];
+            self.orphan_block(selfish, height, withheld_reward);
+            self.pending_fees += withheld_reward - self.reward_schedule.reward(height);
+            self.orphaned_blocks += 1;
+            return false;
+        }
+
+        if lead <= 2 {
+            self.stakers[selfish].publish_all();
+        } else {
+            self.stakers[selfish].publish_one();
+        }
+        self.orphan_block(producer, block_height, reward);
+        self.pending_fees += reward - self.reward_schedule.reward(block_height);
+        self.orphaned_blocks += 1;
+        true
+    }
+
+    /// Lets a second staker find a competing block before the first one has
+    /// propagated, then settles which of the two the chain keeps.
+    fn resolve_fork<R: Rng + ?Sized>(
+        &mut self,
+        first: usize,
+        model: &PropagationModel,
+        reward: Amount,
+        rng: &mut R,
+    ) -> Result<(), SimError> {
+        if rng.gen::<f64>() >= model.fork_probability() {
+            return Ok(());
+        }
+
+        let second = match self.pick_staker(true, rng) {
+            Some(pos) if pos != first => pos,
+            _ => return Ok(()),
+        };
+        if !self.hit_stake(second, reward, rng)? {
+            return Ok(());
+        }
+        self.book_income(self.block_height, reward, false);
+
+        let delay = rng.gen_range(0.0, model.latency.max(f64::MIN_POSITIVE));
+        let loser = if rng.gen::<f64>() < model.first_block_wins(delay) {
+            second
+        } else {
+            first
+        };
+        self.orphan_block(loser, self.block_height, reward);
+        self.orphaned_blocks += 1;
+        Ok(())
+    }
+
+    /// Lets the staker at `pos` try to produce the current block, with its
+    /// pool's hardware if it delegates. The pool keeps its fee on success.
+    fn hit_stake<R: Rng + ?Sized>(
+        &mut self,
+        pos: usize,
+        reward: Amount,
+        rng: &mut R,
+    ) -> Result<bool, SimError> {
+        let operator = self.stakers[pos].pool().map(|id| id as usize);
+        let mut fee = Amount::ZERO;
+        if let Some(id) = operator {
+            let load = self
+                .stakers
+                .iter()
+                .filter(|p| p.is_active() && p.pool() == Some(id as u64))
+                .map(|p| p.denoms().count())
+                .sum();
+            self.pools[id].set_load(load);
+            fee = self.pools[id].fee_on(reward);
+        }
+
+        let pools = &self.pools;
+        let found = self.stakers[pos].hit_stake(
+            self.block_height,
+            reward - fee,
+            self.capacity.as_ref(),
+            operator.map(|id| &pools[id]),
+            &self.maturity.at(self.block_height),
+            rng,
+        )?;
+        if let (true, Some(id)) = (found, operator) {
+            self.pools[id].add_fee(fee);
+        }
+        Ok(found)
+    }
+
+    fn orphan_block(&mut self, pos: usize, block_height: u64, reward: Amount) {
+        let mut fee = Amount::ZERO;
+        if let Some(id) = self.stakers[pos].pool() {
+            let pool = &mut self.pools[id as usize];
+            fee = pool.fee_on(reward);
+            pool.remove_fee(fee);
+        }
+        let maturity = self.maturity.at(block_height);
+        self.stakers[pos].orphan_block(block_height, reward - fee, &maturity);
+        self.book_income(block_height, reward, true);
+    }
+
+    /// Draws the staker that finds the kernel, weighted by stake weight.
+    fn pick_staker<R: Rng + ?Sized>(&mut self, online_only: bool, rng: &mut R) -> Option<usize> {
+        let eligible = |p: &Staker| p.is_staking() && (!online_only || p.is_online());
+        let total_weight: u64 = self
+            .stakers
+            .iter()
+            .filter(|p| eligible(p))
+            .map(Staker::stake_weight)
+            .sum();
+        if total_weight == 0 {
+            return None;
+        }
+
+        let mut start = 0.0;
+        for staker in &mut self.stakers {
+            if !eligible(staker) {
+                staker.set_range(Range {
+                    start: 0.0,
+                    end: 0.0,
+                });
+                continue;
+            }
+
+            let pct = staker.stake_probability(total_weight);
+            staker.set_range(Range {
+                start,
+                end: start + pct,
+            });
+            start += pct;
+        }
+
+        let winning_pct = rng.gen_range(0.0, start);
+        self.stakers
+            .iter()
+            .position(|p| eligible(p) && p.range().contains(&winning_pct))
+    }
+}
+
+/// Draws how many events happen in a block at the given rate.
+fn poisson<R: Rng + ?Sized>(rate: f64, rng: &mut R) -> u64 {
+    if rate > 0.0 {
+        Poisson::new(rate).unwrap().sample(rng)
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adversary::AdversarySpec;
+    use crate::denom::Denom;
+    use crate::exchange::ExchangeReport;
+    use crate::pool::PoolReport;
+    use crate::population::PopulationPhase;
+    use crate::weight::{self, WeightFunction};
+    use rand::{SeedableRng, rngs::StdRng};
+
+    /// A network with its generated stakers, and the random stream to run it.
+    fn network(seed: u64) -> (Network, StdRng) {
+        network_with(seed, |_| {})
+    }
+
+    /// Like `network`, with `setup` applied before the stakers are created.
+    fn network_with(seed: u64, setup: impl FnOnce(&mut Network)) -> (Network, StdRng) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut network = Network::new();
+        setup(&mut network);
+        network.create_stakers(&mut rng).unwrap();
+        (network, rng)
+    }
+
+    fn run(network: &mut Network, blocks: u64, rng: &mut StdRng) {
+        for _ in 0..blocks {
+            network.step(rng).unwrap();
+        }
+    }
+
+    /// `count` summed over every staker.
+    fn total(network: &Network, count: fn(&Staker) -> u64) -> u64 {
+        network.stakers().iter().map(count).sum()
+    }
+
+    /// Every coin of the supply: in wallets, in the float or owed as fees.
+    fn held(network: &Network) -> Amount {
+        network
+            .stakers()
+            .iter()
+            .map(|p| p.denoms().balance())
+            .sum::<Amount>()
+            + network.float()
+            + network.pending_fees()
+    }
+
+    #[test]
+    fn test_create_stakers_distributes_supply() {
+        let (network, _) = network(3);
+        let total: Amount = network.stakers().iter().map(Staker::start_balance).sum();
+        assert_eq!(total, network.total_supply());
+        assert_eq!(network.participation_rate(), 1.0);
+    }
+
+    #[test]
+    fn test_non_staking_fraction() {
+        let (mut network, mut rng) = network_with(3, |p| p.set_non_staking_fraction(0.4));
+        let total: Amount = network.stakers().iter().map(Staker::start_balance).sum();
+        assert_eq!(total + network.float(), network.total_supply());
+        assert!((network.participation_rate() - 0.6).abs() < 1e-9);
+
+        let start = network.float();
+        run(&mut network, 100, &mut rng);
+        assert_eq!(network.float(), start);
+        assert!(network.participation_rate() > 0.6);
+    }
+
+    #[test]
+    fn test_population_conserves_supply() {
+        let (mut network, mut rng) = network(4);
+        let start = network.block_height();
+        let stakers = network.stakers().len();
+        let balances: Vec<Amount> = network
+            .stakers()
+            .iter()
+            .map(|p| p.denoms().balance())
+            .collect();
+        network.set_population(PopulationSchedule::new(vec![
+            PopulationPhase {
+                heights: start..start + 50,
+                arrival_rate: 0.0,
+                departure_rate: 1.0,
+            },
+            PopulationPhase {
+                heights: start + 50..start + 100,
+                arrival_rate: 1.0,
+                departure_rate: 0.0,
+            },
+        ]));
+
+        for _ in 0..50 {
+            network.update_population(&mut rng).unwrap();
+            network.block_height += 1;
+        }
+        // Leavers take everything they held to the float.
+        let left: Vec<usize> = (0..stakers)
+            .filter(|i| !network.stakers()[*i].is_active())
+            .collect();
+        assert!(!left.is_empty());
+        assert!(
+            left.iter()
+                .all(|i| network.stakers()[*i].denoms().balance().is_zero())
+        );
+        let float: Amount = left.iter().map(|i| balances[*i]).sum();
+        assert_eq!(network.float(), float);
+
+        for _ in 0..50 {
+            network.update_population(&mut rng).unwrap();
+            network.block_height += 1;
+        }
+        // Newcomers buy their coins from it, and nobody else leaves.
+        let joined = &network.stakers()[stakers..];
+        assert!(!joined.is_empty());
+        assert!(
+            joined
+                .iter()
+                .all(|p| p.is_active() && (start + 50..start + 100).contains(&p.joined_height()))
+        );
+        let bought: Amount = joined.iter().map(Staker::start_balance).sum();
+        assert_eq!(network.float(), float - bought);
+        let leavers = network.stakers().iter().filter(|p| !p.is_active()).count();
+        assert_eq!(leavers, left.len());
+        assert_eq!(held(&network), network.total_supply());
+    }
+
+    #[test]
+    fn test_offline_stakers_forfeit() {
+        let (mut network, mut rng) = network(8);
+        network.set_availability(Some(AvailabilityModel {
+            failure_rate: 0.05,
+            recovery_rate: 0.05,
+            strength_scale: 0.0,
+        }));
+        run(&mut network, 500, &mut rng);
+        network.update_stakers();
+
+        // Slots are only missed while offline, and the block still gets made.
+        assert!(total(&network, Staker::missed_count) > 0);
+        assert!(
+            network
+                .stakers()
+                .iter()
+                .filter(|p| p.missed_count() > 0)
+                .all(|p| p.uptime_pct() < 1.0)
+        );
+        let stakes = total(&network, Staker::stake_count);
+        assert_eq!(stakes + total(&network, Staker::orphaned_count), 500);
+    }
+
+    /// Every node drops out after the first block and never comes back.
+    fn always_offline() -> Option<AvailabilityModel> {
+        Some(AvailabilityModel {
+            failure_rate: 1.0,
+            recovery_rate: 0.0,
+            strength_scale: 0.0,
+        })
+    }
+
+    #[test]
+    fn test_offline_winner_forfeits_to_online_staker() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let mut network = Network::new();
+        let member = network
+            .add_staker(Amount::from_coins(9_000), DenomStrategy::OnlySmall, 0.0)
+            .unwrap() as usize;
+        let solo = network
+            .add_staker(Amount::from_coins(100_000), DenomStrategy::OnlySmall, 0.0)
+            .unwrap() as usize;
+        // Pool members are staked by the operator, which never goes offline.
+        network.add_pool(Pool::new(0, 3.0, 20_000, 0.0, Amount::from_coins(10_000)));
+        network.set_availability(always_offline());
+        network.step(&mut rng).unwrap();
+        let solo_stakes = network.stakers()[solo].stake_count();
+        let member_stakes = network.stakers()[member].stake_count();
+
+        run(&mut network, 50, &mut rng);
+        let stakers = network.stakers();
+        assert_eq!(stakers[solo].stake_count(), solo_stakes);
+        assert_eq!(stakers[member].stake_count(), member_stakes + 50);
+        assert!(stakers[solo].missed_count() > 40);
+        assert_eq!(stakers[member].missed_count(), 0);
+    }
+
+    #[test]
+    fn test_block_lost_when_everyone_is_offline() {
+        let (mut network, mut rng) = network(8);
+        network.set_availability(always_offline());
+        network.step(&mut rng).unwrap();
+        let supply = network.total_supply();
+        let stakes = total(&network, Staker::stake_count);
+        let missed = total(&network, Staker::missed_count);
+
+        run(&mut network, 10, &mut rng);
+        assert_eq!(network.total_supply(), supply);
+        assert_eq!(total(&network, Staker::stake_count), stakes);
+        assert_eq!(total(&network, Staker::missed_count), missed + 10);
+    }
+
+    #[test]
+    fn test_capacity_misses_go_to_other_stakers() {
+        let (mut network, mut rng) = network(12);
+        network.set_capacity(Some(CapacityModel {
+            hash_rate: 1.0,
+            strength_scale: 1.0,
+            search_interval: 100.0,
+        }));
+        run(&mut network, 200, &mut rng);
+
+        assert!(total(&network, Staker::orphaned_count) > 0);
+        assert_eq!(total(&network, Staker::stake_count), 200);
+    }
+
+    #[test]
+    fn test_forks_reverse_the_losing_block() {
+        let (mut network, mut rng) = network(14);
+        network.set_propagation(Some(PropagationModel {
+            latency: 60.0,
+            block_time: 60.0,
+        }));
+        run(&mut network, 300, &mut rng);
+
+        assert!(network.orphaned_blocks() > 0);
+        assert_eq!(
+            total(&network, Staker::orphaned_count),
+            network.orphaned_blocks()
+        );
+        assert_eq!(total(&network, Staker::stake_count), 300);
+    }
+
+    #[test]
+    fn test_selfish_staker_orphans_honest_blocks() {
+        let (mut network, mut rng) = network(16);
+        let spec = AdversarySpec {
+            behavior: Behavior::Selfish { gamma: 1.0 },
+            balance: network.total_supply() / 2,
+        };
+        let id = spec.apply(&mut network).unwrap();
+        run(&mut network, 2_000, &mut rng);
+
+        let reward = network.reward_schedule().reward(network.block_height());
+        let adversary = &network.stakers()[id as usize];
+        assert!(network.orphaned_blocks() > 0);
+        assert_eq!(adversary.rewards_earned(), reward * adversary.stake_count());
+        assert!(adversary.rewards_earned().to_sat() as f64 > adversary.fair_rewards());
+    }
+
+    #[test]
+    fn test_pool_members_pay_fees() {
+        let (mut network, mut rng) = network(18);
+        let id = network.add_pool(Pool::new(
+            0,
+            3.0,
+            20_000,
+            0.1,
+            Amount::from_coins(1_000_000),
+        ));
+        run(&mut network, 300, &mut rng);
+
+        // Every small enough staker delegates, and every block a member
+        // stakes pays the operator its cut.
+        let reward = network.reward_schedule().reward(network.block_height());
+        let pool = &network.pools()[id as usize];
+        let members: Vec<&Staker> = network
+            .stakers()
+            .iter()
+            .filter(|p| p.pool() == Some(id))
+            .collect();
+        let blocks: u64 = members.iter().map(|p| p.stake_count()).sum();
+        let eligible = network
+            .stakers()
+            .iter()
+            .filter(|p| p.start_balance() <= pool.max_member_balance)
+            .count();
+        assert_eq!(members.len(), eligible);
+        assert!(blocks > 0);
+        assert_eq!(pool.fees(), pool.fee_on(reward) * blocks);
+
+        let report = PoolReport::new(network.pools(), network.stakers());
+        assert_eq!(report.nakamoto_coefficient, 1);
+    }
+
+    #[test]
+    fn test_holding_exchange_never_stakes() {
+        let (mut network, mut rng) = network_with(20, |p| {
+            p.set_exchanges(vec![Exchange::new(
+                0.3,
+                false,
+                DenomStrategy::OnlySmall,
+                2.0,
+                1.0,
+            )])
+        });
+        let id = network.exchanges()[0].staker().unwrap() as usize;
+        let start = network.stakers()[id].denoms().balance();
+        assert_eq!(start, network.total_supply().fraction(0.3));
+        assert!((network.participation_rate() - 0.7).abs() < 1e-9);
+        for _ in 0..200 {
+            network.update_exchanges(&mut rng).unwrap();
+        }
+
+        // Without fees whatever one side sends the other receives.
+        let report = ExchangeReport::new(network.exchanges(), network.stakers());
+        let row = &report.rows[0];
+        assert!(row.deposits > 0 && row.withdrawals > 0);
+        assert_eq!(
+            network.stakers()[id].denoms().balance(),
+            start + row.deposited - row.withdrawn
+        );
+        assert_eq!(held(&network), network.total_supply());
+        assert!(network.stakers()[id].denoms().immature_balance() > Amount::ZERO);
+        run(&mut network, 200, &mut rng);
+        assert_eq!(network.stakers()[id].stake_count(), 0);
+    }
+
+    #[test]
+    fn test_transactions_move_coins() {
+        let (mut network, mut rng) = network_with(22, |p| p.set_non_staking_fraction(0.2));
+        network.set_transactions(Some(TransactionModel {
+            rate: 5.0,
+            max_fraction: 0.5,
+            external: 0.5,
+        }));
+        let supply = held(&network);
+        for _ in 0..100 {
+            network.update_transactions(&mut rng).unwrap();
+        }
+
+        // Received coins and change wait out the transfer maturity.
+        let transfer = network.maturity().at(network.block_height()).transfer;
+        assert!(total(&network, Staker::transaction_count) > 400);
+        assert_eq!(held(&network), supply);
+        let received: Vec<&Denom> = network
+            .stakers()
+            .iter()
+            .flat_map(|p| p.denoms().immature())
+            .collect();
+        assert!(!received.is_empty());
+        assert!(
+            received
+                .iter()
+                .all(|p| !p.is_stake() && p.mature_height() == p.created_height() + transfer)
+        );
+    }
+
+    #[test]
+    fn test_fees_go_to_block_producers() {
+        let (mut network, mut rng) = network_with(24, |p| p.set_non_staking_fraction(0.1));
+        network.set_transactions(Some(TransactionModel {
+            rate: 2.0,
+            max_fraction: 0.2,
+            external: 0.2,
+        }));
+        network.set_fees(Some(FeeModel::default()));
+        let start = held(&network);
+        run(&mut network, 300, &mut rng);
+
+        // Producers get the subsidy plus the fees, and only the subsidy is new.
+        let report = network.fee_report();
+        let subsidy: Amount = report.eras.iter().map(|p| p.subsidy).sum();
+        let fees: Amount = report.eras.iter().map(|p| p.fees).sum();
+        let rewards: Amount = network.stakers().iter().map(Staker::rewards_earned).sum();
+        assert!(fees > Amount::ZERO);
+        assert_eq!(rewards, subsidy + fees);
+        assert_eq!(held(&network), start + subsidy);
+    }
+
+    #[test]
+    fn test_stake_mods_scale_weight() {
+        let (mut network, mut rng) = network(26);
+        let weight = network.total_weight();
+        network.set_stake_mods(StakeMods::new(vec!["all:0.5".parse().unwrap()]));
+        let halved = network.total_weight() as f64 / weight as f64;
+        assert!((halved - 0.5).abs() < 1e-6);
+
+        let mods = StakeMods::new(vec![
+            "young:2_000:0.5".parse().unwrap(),
+            "reward:0.5".parse().unwrap(),
+        ]);
+        network.set_stake_mods(mods.clone());
+        run(&mut network, 200, &mut rng);
+        let block_height = network.block_height() - 1;
+        for staker in network.stakers().iter().filter(|p| p.is_active()) {
+            let weight: u64 = staker
+                .denoms()
+                .mature()
+                .iter()
+                .map(|p| weight::modified_weight(p.weight(), mods.stake_mod(p, block_height)))
+                .sum();
+            assert_eq!(staker.stake_weight(), weight);
+        }
+    }
+
+    #[test]
+    fn test_coin_days_consensus_never_locks_inputs() {
+        let (mut network, mut rng) = network(27);
+        network.set_weight_rule("coin-days:500:20_000".parse().unwrap());
+        run(&mut network, 300, &mut rng);
+
+        let stakes: u64 = network
+            .stakers()
+            .iter()
+            .map(|p| p.denoms().total_stake_count())
+            .sum();
+        assert!(stakes > 250);
+        assert!(
+            network
+                .stakers()
+                .iter()
+                .all(|p| { p.denoms().immature().iter().all(|denom| denom.is_stake()) })
+        );
+    }
+
+    #[test]
+    fn test_zerocoin_mint_fees_go_to_producers() {
+        let (mut network, mut rng) = network(28);
+        network
+            .set_zerocoin(Some(ZerocoinModel::default()))
+            .unwrap();
+        let start = held(&network);
+        run(&mut network, 300, &mut rng);
+
+        let report = network.fee_report();
+        let subsidy: Amount = report.eras.iter().map(|p| p.subsidy).sum();
+        let fees: Amount = report.eras.iter().map(|p| p.fees).sum();
+        let mint_fees: Amount = network
+            .zerocoin_report()
+            .rows
+            .iter()
+            .map(|p| p.mint_fees)
+            .sum();
+        assert!(fees > Amount::ZERO);
+        assert_eq!(fees + network.pending_fees(), mint_fees);
+        assert_eq!(held(&network), start + subsidy);
+    }
+
+    #[test]
+    fn test_consensus_forks_split_results() {
+        let (mut network, mut rng) = network(29);
+        let start = network.block_height();
+        network.set_consensus(ConsensusSchedule::new(vec![
+            format!("{}:weight:linear", start + 100).parse().unwrap(),
+            format!("{}:maturity:10:500:50", start + 200)
+                .parse()
+                .unwrap(),
+            format!("{}:zerocoin:default", start + 200).parse().unwrap(),
+        ]));
+        run(&mut network, 300, &mut rng);
+
+        assert_eq!(network.weight_rule(), "linear".parse().unwrap());
+        assert!(network.zerocoin().is_some());
+        assert_eq!(network.maturity().at(start + 200).input_lock, 500);
+        assert_eq!(
+            network.maturity().at(start + 199),
+            crate::maturity::Maturity::default()
+        );
+
+        let report = network.consensus_report().unwrap();
+        let starts: Vec<u64> = report.periods.iter().map(|p| p.start_height).collect();
+        assert_eq!(starts, vec![start, start + 100, start + 200]);
+        assert_eq!(report.periods[2].changes.len(), 2);
+        for period in &report.periods {
+            let blocks: u64 = period.rows.iter().map(|p| p.blocks).sum();
+            assert!(blocks > 80 && blocks <= 100);
+        }
+    }
+
+    #[test]
+    fn test_gradual_migration_to_ringct() {
+        let (mut network, mut rng) = network(30);
+        network
+            .set_zerocoin(Some(ZerocoinModel::default()))
+            .unwrap();
+        network.set_migration_model(Some("100:0.2:0.01".parse().unwrap()));
+        let start = network.block_height();
+        network.set_consensus(ConsensusSchedule::new(vec![
+            format!("{}:ringct", start + 50).parse().unwrap(),
+        ]));
+        let supply = held(&network);
+        run(&mut network, 1_000, &mut rng);
+        let subsidy: Amount = network.fee_report().eras.iter().map(|p| p.subsidy).sum();
+        assert_eq!(held(&network), supply + subsidy);
+
+        // Everyone but the `never` share has migrated long before the end.
+        // Some of those never held zerocoins, their balance being too small
+        // for their strategy's denomination.
+        let report = network.migration_report().unwrap();
+        assert_eq!(report.start_height, start + 50);
+        let never = report.rows.last().unwrap();
+        assert_eq!(never.max_delay, None);
+        let laggards = network
+            .stakers()
+            .iter()
+            .filter(|p| p.denoms().holds_zerocoins())
+            .count();
+        assert!(laggards <= never.stakers);
+        let stakers = network.stakers().len();
+        assert!(laggards > stakers / 10 && laggards < stakers / 3);
+
+        // Windows are a quarter of the mean delay and count every block.
+        let windows = &report.windows;
+        assert!(windows.iter().all(|p| p.blocks == 25));
+        assert!(windows.windows(2).all(|p| {
+            p[1].start_height == p[0].start_height + 25
+                && p[1].migrated_share >= p[0].migrated_share
+        }));
+        let balance: Amount = report.rows.iter().map(|p| p.balance).sum();
+        let migrated = 1.0 - never.balance.to_sat() as f64 / balance.to_sat() as f64;
+        assert!((windows.last().unwrap().migrated_share - migrated).abs() < 1e-9);
+
+        // Migrated coins wait out the transfer maturity, longer than a
+        // window, so at first only those still waiting find blocks. Later
+        // the migrated find more than their share.
+        assert_eq!(windows[0].migrated_blocks, 0);
+        let late = &windows[windows.len() / 2];
+        assert!(late.migrated_blocks as f64 > late.migrated_share * late.blocks as f64);
+    }
+
+    #[test]
+    fn test_errors_instead_of_panicking() {
+        let mut rng = StdRng::seed_from_u64(31);
+        let mut network = Network::new();
+        assert!(matches!(
+            network.step(&mut rng),
+            Err(SimError::EmptySelection { .. })
+        ));
+        assert!(matches!(
+            network.add_staker(Amount::MAX_MONEY, DenomStrategy::OnlySmall, 0.0),
+            Err(SimError::OutOfRange(_))
+        ));
+        assert!(network.stakers().is_empty());
+    }
+
+    #[test]
+    fn test_coin_age_weights_follow_height() {
+        let (mut network, mut rng) = network(25);
+        let rule: WeightRule = "coin-age:5_000".parse().unwrap();
+        network.set_weight_rule(rule);
+        run(&mut network, 200, &mut rng);
+
+        // The block just staked was weighed at the height before this one.
+        let block_height = network.block_height() - 1;
+        for staker in network.stakers().iter().filter(|p| p.is_active()) {
+            let weight: u64 = staker
+                .denoms()
+                .mature()
+                .iter()
+                .map(|p| rule.weight(p, block_height))
+                .sum();
+            assert_eq!(staker.stake_weight(), weight);
+        }
+    }
+
+    #[test]
+    fn test_block_reward_eras() {
+        let schedule = RewardSchedule::default();
+        assert_eq!(schedule.reward(0), Amount::from_coins(50));
+        assert_eq!(
+            schedule.reward(REWARD_REDUCTION_BLOCK),
+            Amount::from_coins(40)
+        );
+        assert_eq!(
+            schedule.reward(REWARD_REDUCTION_BLOCK * 4),
+            Amount::from_coins(10)
+        );
+        assert_eq!(
+            schedule.reward(REWARD_REDUCTION_BLOCK * 10),
+            Amount::from_coins(10)
+        );
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+use crate::amount::{self, Amount};
+use crate::denom::{DENOM_THRESHOLD_MAX, DENOM_THRESHOLD_MIN};
+use crate::parse::number;
+use crate::staker::Staker;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// A cold staking operator or pool. Members keep their coins but the
+/// operator's node hashes their outputs, so kernels are searched with the
+/// operator's computer and threshold, shared over every member's outputs. The
+/// operator keeps `fee` of each reward and is always online.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Pool {
+    pub id: u64,
+    pub computer_strength: f64,
+    pub denom_threshold: u64,
+    pub fee: f64,
+    /// Stakers holding at most this much delegate to a pool.
+    pub max_member_balance: Amount,
+    /// Outputs the operator hashes across all members.
+    load: u64,
+    fees: Amount,
+}
+
+impl Pool {
+    pub fn new(
+        id: u64,
+        computer_strength: f64,
+        denom_threshold: u64,
+        fee: f64,
+        max_member_balance: Amount,
+    ) -> Self {
+        Self {
+            id,
+            computer_strength,
+            denom_threshold,
+            fee,
+            max_member_balance,
+            load: 0,
+            fees: Amount::ZERO,
+        }
+    }
+
+    pub fn load(&self) -> u64 {
+        self.load
+    }
+
+    pub fn set_load(&mut self, load: u64) {
+        self.load = load;
+    }
+
+    /// Fees kept by the operator so far.
+    pub fn fees(&self) -> Amount {
+        self.fees
+    }
+
+    /// The operator's cut of `reward`.
+    pub fn fee_on(&self, reward: Amount) -> Amount {
+        reward.fraction(self.fee)
+    }
+
+    pub fn add_fee(&mut self, fee: Amount) {
+        self.fees += fee;
+    }
+
+    pub fn remove_fee(&mut self, fee: Amount) {
+        self.fees -= fee;
+    }
+}
+
+/// Parses `STRENGTH:THRESHOLD:FEE:MAX_COINS`. The id is set when the pool is
+/// added to a network.
+impl FromStr for Pool {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() != 4 {
+            return Err(format!(
+                "Expected STRENGTH:THRESHOLD:FEE:MAX_COINS, got {}.",
+                s
+            ));
+        }
+        let threshold = number::<f64>(parts[1])? as u64;
+        let fee: f64 = number(parts[2])?;
+        if !(DENOM_THRESHOLD_MIN..=DENOM_THRESHOLD_MAX).contains(&threshold) {
+            return Err(format!(
+                "Threshold must be between {} and {}, got {}.",
+                DENOM_THRESHOLD_MIN, DENOM_THRESHOLD_MAX, threshold
+            ));
+        }
+        if !(0.0..=1.0).contains(&fee) {
+            return Err(format!("Fee must be between 0 and 1, got {}.", fee));
+        }
+
+        Ok(Pool::new(
+            0,
+            number(parts[0])?,
+            threshold,
+            fee,
+            parts[3].parse::<Amount>()?,
+        ))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PoolRow {
+    pub id: u64,
+    pub members: usize,
+    pub fee: f64,
+    pub fees: Amount,
+    pub stake_count: u64,
+    pub orphaned_count: u64,
+    pub block_share: f64,
+    pub members_change_pct: f64,
+}
+
+/// Block production per operator. Solo stakers operate for themselves.
+#[derive(Debug, Serialize)]
+pub struct PoolReport {
+    pub rows: Vec<PoolRow>,
+    pub solo_change_pct: f64,
+    pub operators: usize,
+    /// Fewest operators that together produced more than half the blocks.
+    pub nakamoto_coefficient: usize,
+}
+
+impl PoolReport {
+    pub fn new(pools: &[Pool], stakers: &[Staker]) -> Self {
+        let mut blocks: HashMap<(bool, u64), u64> = HashMap::new();
+        for staker in stakers {
+            let operator = match staker.pool() {
+                Some(pool) => (true, pool),
+                None => (false, staker.id()),
+            };
+            *blocks.entry(operator).or_insert(0) += staker.stake_count();
+        }
+        let total: u64 = blocks.values().sum();
+
+        let rows = pools
+            .iter()
+            .map(|pool| {
+                let members: Vec<&Staker> = stakers
+                    .iter()
+                    .filter(|p| p.pool() == Some(pool.id))
+                    .collect();
+                let stake_count = blocks.get(&(true, pool.id)).copied().unwrap_or(0);
+                PoolRow {
+                    id: pool.id,
+                    members: members.len(),
+                    fee: pool.fee,
+                    fees: pool.fees(),
+                    stake_count,
+                    orphaned_count: members.iter().map(|p| p.orphaned_count()).sum(),
+                    block_share: share(stake_count, total),
+                    members_change_pct: mean_change(&members),
+                }
+            })
+            .collect();
+
+        let solo: Vec<&Staker> = stakers.iter().filter(|p| p.pool().is_none()).collect();
+        let mut counts: Vec<u64> = blocks.values().copied().filter(|p| *p > 0).collect();
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        let mut produced = 0;
+        let nakamoto_coefficient = counts
+            .iter()
+            .position(|p| {
+                produced += p;
+                produced * 2 > total
+            })
+            .map_or(0, |p| p + 1);
+
+        Self {
+            rows,
+            solo_change_pct: mean_change(&solo),
+            operators: counts.len(),
+            nakamoto_coefficient,
+        }
+    }
+}
+
+fn share(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+fn mean_change(stakers: &[&Staker]) -> f64 {
+    if stakers.is_empty() {
+        0.0
+    } else {
+        stakers.iter().map(|p| p.change_pct()).sum::<f64>() / stakers.len() as f64
+    }
+}
+
+impl fmt::Display for PoolReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>6} {:>8} {:>6} {:>12} {:>8} {:>9} {:>8} {:>10}",
+            "pool", "members", "fee%", "fees", "blocks", "orphaned", "share%", "change"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:>6} {:>8} {:>6.2} {:>12} {:>8} {:>9} {:>8.2} {:>10.4}",
+                row.id,
+                row.members,
+                row.fee * 100.0,
+                row.fees.to_sat() / amount::COIN,
+                row.stake_count,
+                row.orphaned_count,
+                row.block_share * 100.0,
+                row.members_change_pct
+            )?;
+        }
+        writeln!(f, "Solo stakers' mean change: {:.4}", self.solo_change_pct)?;
+        writeln!(
+            f,
+            "Nakamoto coefficient: {} of {} operators.",
+            self.nakamoto_coefficient, self.operators
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pool() {
+        let pool: Pool = "2.0:15000:0.05:1_000".parse().unwrap();
+        assert_eq!(pool.denom_threshold, 15_000);
+        assert_eq!(pool.fee_on(Amount::from_sat(100)), Amount::from_sat(5));
+        assert_eq!(pool.max_member_balance, Amount::from_coins(1_000));
+        assert!("2.0:15000:1.5:1000".parse::<Pool>().is_err());
+        assert!("2.0:15000:0.05".parse::<Pool>().is_err());
+    }
+}
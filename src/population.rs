@@ -0,0 +1,118 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::parse::number;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Arrival and departure rates over a range of block heights. Rates are the
+/// expected number of stakers joining or leaving per block.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PopulationPhase {
+    pub heights: Range<u64>,
+    pub arrival_rate: f64,
+    pub departure_rate: f64,
+}
+
+/// Parses `START:END:ARRIVALS:DEPARTURES`.
+impl FromStr for PopulationPhase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() != 4 {
+            return Err(format!(
+                "Expected START:END:ARRIVALS:DEPARTURES, got {}.",
+                s
+            ));
+        }
+        let phase = PopulationPhase {
+            heights: number(parts[0])?..number(parts[1])?,
+            arrival_rate: number(parts[2])?,
+            departure_rate: number(parts[3])?,
+        };
+        if phase.heights.start >= phase.heights.end {
+            return Err(format!("START must be below END in {}.", s));
+        }
+        if phase.arrival_rate < 0.0 || phase.departure_rate < 0.0 {
+            return Err(format!("Rates must not be negative in {}.", s));
+        }
+        Ok(phase)
+    }
+}
+
+/// Arrival/departure process for the staker set. Overlapping phases add up.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PopulationSchedule {
+    phases: Vec<PopulationPhase>,
+}
+
+impl PopulationSchedule {
+    pub fn new(phases: Vec<PopulationPhase>) -> Self {
+        Self { phases }
+    }
+
+    pub fn phases(&self) -> &[PopulationPhase] {
+        &self.phases
+    }
+
+    /// Expected arrivals and departures for the block at `block_height`.
+    pub fn rates(&self, block_height: u64) -> (f64, f64) {
+        self.phases
+            .iter()
+            .filter(|p| p.heights.contains(&block_height))
+            .fold((0.0, 0.0), |(arrivals, departures), p| {
+                (arrivals + p.arrival_rate, departures + p.departure_rate)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_add_up() {
+        let schedule = PopulationSchedule::new(vec![
+            "0:100:0.5:0".parse().unwrap(),
+            "50:150:0.25:0.1".parse().unwrap(),
+        ]);
+        assert_eq!(schedule.rates(10), (0.5, 0.0));
+        assert_eq!(schedule.rates(75), (0.75, 0.1));
+        assert_eq!(schedule.rates(150), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_parse_phase() {
+        assert!("1:2:3".parse::<PopulationPhase>().is_err());
+        assert!("1:2:-1:0".parse::<PopulationPhase>().is_err());
+        assert!("2:2:1:0".parse::<PopulationPhase>().is_err());
+        assert!("3:2:1:0".parse::<PopulationPhase>().is_err());
+        assert!("1.5:2:1:0".parse::<PopulationPhase>().is_err());
+        assert!("-1:2:1:0".parse::<PopulationPhase>().is_err());
+        let phase: PopulationPhase = "300_000:400_000:0.01:0.002".parse().unwrap();
+        assert_eq!(phase.heights, 300_000..400_000);
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::error::SimError;
+use crate::network::{Network, SimRng};
+use crate::parse::number;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Block propagation across the network. A staker that finds a kernel within
+/// `latency` seconds of another block, before hearing about it, produces a
+/// competing block and one of the two ends up orphaned.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct PropagationModel {
+    /// Seconds for a block to reach the rest of the network.
+    pub latency: f64,
+    /// Target seconds between blocks.
+    pub block_time: f64,
+}
+
+impl PropagationModel {
+    /// Chance that another kernel is found before the first block has
+    /// propagated, with kernels arriving as a Poisson process.
+    pub fn fork_probability(&self) -> f64 {
+        if self.block_time <= 0.0 {
+            return 1.0;
+        }
+        1.0 - (-self.latency / self.block_time).exp()
+    }
+
+    /// Chance the first of two competing blocks wins when the second one
+    /// was found `delay` seconds later. The later it comes, the more of the
+    /// network has already built on the first.
+    pub fn first_block_wins(&self, delay: f64) -> f64 {
+        if self.latency <= 0.0 {
+            return 1.0;
+        }
+        0.5 + 0.5 * (delay / self.latency).min(1.0)
+    }
+}
+
+/// Parses `LATENCY:BLOCK_TIME`, both in seconds.
+impl FromStr for PropagationModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<f64> = s.split(':').map(number).collect::<Result<_, _>>()?;
+        match parts.as_slice() {
+            [latency, block_time] if *latency >= 0.0 && *block_time > 0.0 => Ok(Self {
+                latency: *latency,
+                block_time: *block_time,
+            }),
+            _ => Err(format!("Expected LATENCY:BLOCK_TIME, got {}.", s)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SweepRow {
+    pub latency: f64,
+    pub block_time: f64,
+    pub blocks: u64,
+    pub orphaned_blocks: u64,
+    pub orphan_rate: f64,
+    pub expected_orphan_rate: f64,
+}
+
+/// Orphan rates for every combination of latency and block time, each run
+/// from the same network state and random stream.
+#[derive(Debug, Serialize)]
+pub struct Sweep {
+    rows: Vec<SweepRow>,
+}
+
+impl Sweep {
+    pub fn run(
+        network: &Network,
+        rng: &SimRng,
+        latencies: &[f64],
+        block_times: &[f64],
+        blocks: u64,
+    ) -> Result<Self, SimError> {
+        let mut rows = Vec::new();
+        for block_time in block_times {
+            for latency in latencies {
+                let model = PropagationModel {
+                    latency: *latency,
+                    block_time: *block_time,
+                };
+                let mut network = network.clone();
+                let mut rng = rng.clone();
+                network.set_propagation(Some(model));
+                let start = network.orphaned_blocks();
+                for _ in 0..blocks {
+                    network.step(&mut rng)?;
+                }
+                let orphaned_blocks = network.orphaned_blocks() - start;
+
+                rows.push(SweepRow {
+                    latency: *latency,
+                    block_time: *block_time,
+                    blocks,
+                    orphaned_blocks,
+                    orphan_rate: if blocks == 0 {
+                        0.0
+                    } else {
+                        orphaned_blocks as f64 / blocks as f64
+                    },
+                    expected_orphan_rate: model.fork_probability(),
+                });
+            }
+        }
+        Ok(Self { rows })
+    }
+
+    pub fn rows(&self) -> &[SweepRow] {
+        &self.rows
+    }
+}
+
+impl fmt::Display for Sweep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>10} {:>12} {:>10} {:>10} {:>10} {:>10}",
+            "latency", "block time", "blocks", "orphans", "orphan%", "expected%"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:>10.2} {:>12.2} {:>10} {:>10} {:>9.3}% {:>9.3}%",
+                row.latency,
+                row.block_time,
+                row.blocks,
+                row.orphaned_blocks,
+                row.orphan_rate * 100.0,
+                row.expected_orphan_rate * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_fork_probability() {
+        let none = PropagationModel {
+            latency: 0.0,
+            block_time: 60.0,
+        };
+        let slow = PropagationModel {
+            latency: 6.0,
+            block_time: 60.0,
+        };
+        assert_eq!(none.fork_probability(), 0.0);
+        assert!((slow.fork_probability() - 0.0952).abs() < 0.001);
+        assert_eq!(slow.first_block_wins(0.0), 0.5);
+        assert_eq!(slow.first_block_wins(6.0), 1.0);
+    }
+
+    #[test]
+    fn test_sweep_orphans_grow_with_latency() {
+        let mut rng = SimRng::seed_from_u64(13);
+        let mut network = Network::new();
+        network.create_stakers(&mut rng).unwrap();
+
+        let sweep = Sweep::run(&network, &rng, &[0.0, 30.0], &[60.0], 300).unwrap();
+        assert_eq!(sweep.rows()[0].orphaned_blocks, 0);
+        assert!(sweep.rows()[1].orphaned_blocks > 0);
+    }
+
+    #[test]
+    fn test_parse_model() {
+        assert!("2:60".parse::<PropagationModel>().is_ok());
+        assert!("2:0".parse::<PropagationModel>().is_err());
+        assert!("2".parse::<PropagationModel>().is_err());
+    }
+}
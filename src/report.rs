@@ -0,0 +1,458 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::amount::{self, Amount};
+use crate::denom::DenomStrategy;
+use crate::staker::Staker;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Upper bounds of the starting balance buckets. Anything above the last one
+/// lands in a final open bucket.
+pub static BALANCE_BUCKETS: [Amount; 4] = [
+    Amount::from_coins(1_000),
+    Amount::from_coins(10_000),
+    Amount::from_coins(100_000),
+    Amount::from_coins(1_000_000),
+];
+
+/// Stakers online at least this share of blocks count as always-on nodes.
+pub static ALWAYS_ON_UPTIME: f64 = 0.99;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CohortKey {
+    pub denom_strat: DenomStrategy,
+    pub threshold_decile: u8,
+    pub balance_bucket: u8,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Cohort {
+    #[serde(flatten)]
+    pub key: CohortKey,
+    pub stakers: usize,
+    pub mean_change_pct: f64,
+    pub median_change_pct: f64,
+    pub stake_count: u64,
+    pub orphaned_count: u64,
+    pub orphan_rate: f64,
+    pub missed_count: u64,
+    pub mean_uptime_pct: f64,
+    pub transaction_count: u64,
+    /// Share of the cohort's coins still maturing at the end of the run.
+    pub immature_pct: f64,
+    /// Stake weight per whole coin held.
+    pub weight_per_coin: f64,
+}
+
+/// Always-on nodes against home nodes with downtime, per balance bucket.
+#[derive(Debug, Serialize)]
+pub struct DowntimeRow {
+    pub balance_bucket: u8,
+    pub always_on_stakers: usize,
+    pub always_on_mean_change_pct: f64,
+    pub home_stakers: usize,
+    pub home_mean_change_pct: f64,
+    pub home_mean_uptime_pct: f64,
+    pub home_missed_count: u64,
+}
+
+/// How much of their balance stakers had sitting immature over the run, per
+/// balance bucket.
+#[derive(Debug, Serialize)]
+pub struct MaturityRow {
+    pub balance_bucket: u8,
+    pub stakers: usize,
+    pub mean_immature_pct: f64,
+    pub max_immature_pct: f64,
+}
+
+/// End of run summary of how each group of stakers fared.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    cohorts: Vec<Cohort>,
+    downtime: Vec<DowntimeRow>,
+    maturity: Vec<MaturityRow>,
+}
+
+impl Report {
+    /// Groups stakers by strategy, denom threshold decile and starting
+    /// balance bucket. Stakers must have been updated first so that
+    /// `change_pct` is current.
+    pub fn from_stakers(stakers: &[Staker]) -> Self {
+        let mut thresholds: Vec<u64> = stakers.iter().map(Staker::denom_threshold).collect();
+        thresholds.sort_unstable();
+
+        let mut groups: BTreeMap<CohortKey, Vec<&Staker>> = BTreeMap::new();
+        for staker in stakers {
+            let key = CohortKey {
+                denom_strat: staker.denom_strat(),
+                threshold_decile: threshold_decile(&thresholds, staker.denom_threshold()),
+                balance_bucket: balance_bucket(staker.start_balance()),
+            };
+            groups.entry(key).or_default().push(staker);
+        }
+
+        let cohorts = groups
+            .into_iter()
+            .map(|(key, members)| Cohort::new(key, &members))
+            .collect();
+
+        // Without an availability model everyone is always on and there is
+        // nothing to compare.
+        let downtime = if stakers.iter().any(|p| p.uptime_pct() < 1.0) {
+            (0..=BALANCE_BUCKETS.len() as u8)
+                .map(|bucket| DowntimeRow::new(bucket, stakers))
+                .filter(|p| p.always_on_stakers + p.home_stakers > 0)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let maturity = (0..=BALANCE_BUCKETS.len() as u8)
+            .map(|bucket| MaturityRow::new(bucket, stakers))
+            .filter(|p| p.stakers > 0)
+            .collect();
+
+        Self {
+            cohorts,
+            downtime,
+            maturity,
+        }
+    }
+
+    pub fn cohorts(&self) -> &[Cohort] {
+        &self.cohorts
+    }
+
+    pub fn downtime(&self) -> &[DowntimeRow] {
+        &self.downtime
+    }
+
+    pub fn maturity(&self) -> &[MaturityRow] {
+        &self.maturity
+    }
+}
+
+impl MaturityRow {
+    fn new(balance_bucket: u8, stakers: &[Staker]) -> Self {
+        let immature: Vec<f64> = stakers
+            .iter()
+            .filter(|p| self::balance_bucket(p.start_balance()) == balance_bucket)
+            .map(Staker::mean_immature_pct)
+            .collect();
+
+        Self {
+            balance_bucket,
+            stakers: immature.len(),
+            mean_immature_pct: mean(&immature),
+            max_immature_pct: immature.iter().copied().fold(0.0, f64::max),
+        }
+    }
+}
+
+impl DowntimeRow {
+    fn new(balance_bucket: u8, stakers: &[Staker]) -> Self {
+        let (always_on, home): (Vec<&Staker>, Vec<&Staker>) = stakers
+            .iter()
+            .filter(|p| self::balance_bucket(p.start_balance()) == balance_bucket)
+            .partition(|p| p.uptime_pct() >= ALWAYS_ON_UPTIME);
+        let changes = |members: &[&Staker]| -> Vec<f64> {
+            members
+                .iter()
+                .map(|p| p.change_pct())
+                .filter(|p| p.is_finite())
+                .collect()
+        };
+        let uptimes: Vec<f64> = home.iter().map(|p| p.uptime_pct()).collect();
+
+        Self {
+            balance_bucket,
+            always_on_stakers: always_on.len(),
+            always_on_mean_change_pct: mean(&changes(&always_on)),
+            home_stakers: home.len(),
+            home_mean_change_pct: mean(&changes(&home)),
+            home_mean_uptime_pct: mean(&uptimes),
+            home_missed_count: home.iter().map(|p| p.missed_count()).sum(),
+        }
+    }
+}
+
+impl Cohort {
+    fn new(key: CohortKey, members: &[&Staker]) -> Self {
+        let mut changes: Vec<f64> = members
+            .iter()
+            .map(|p| p.change_pct())
+            .filter(|p| p.is_finite())
+            .collect();
+        changes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let stake_count = members.iter().map(|p| p.stake_count()).sum();
+        let orphaned_count = members.iter().map(|p| p.orphaned_count()).sum();
+        let attempts = stake_count + orphaned_count;
+        let uptimes: Vec<f64> = members.iter().map(|p| p.uptime_pct()).collect();
+        let immature: Amount = members.iter().map(|p| p.balance_immature()).sum();
+        let balance: Amount = members
+            .iter()
+            .map(|p| p.balance_spendable() + p.balance_immature())
+            .sum();
+        let weight: u64 = members.iter().map(|p| p.stake_weight()).sum();
+
+        Self {
+            key,
+            stakers: members.len(),
+            mean_change_pct: mean(&changes),
+            median_change_pct: median(&changes),
+            stake_count,
+            orphaned_count,
+            orphan_rate: if attempts == 0 {
+                0.0
+            } else {
+                orphaned_count as f64 / attempts as f64
+            },
+            missed_count: members.iter().map(|p| p.missed_count()).sum(),
+            mean_uptime_pct: mean(&uptimes),
+            transaction_count: members.iter().map(|p| p.transaction_count()).sum(),
+            immature_pct: if balance.is_zero() {
+                0.0
+            } else {
+                immature.to_sat() as f64 / balance.to_sat() as f64
+            },
+            weight_per_coin: if balance.is_zero() {
+                0.0
+            } else {
+                weight as f64 / (balance.to_sat() / amount::COIN).max(1) as f64
+            },
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<20} {:>7} {:>14} {:>8} {:>12} {:>12} {:>8} {:>8} {:>8} {:>8} {:>10}",
+            "strategy",
+            "decile",
+            "balance",
+            "stakers",
+            "mean chg",
+            "median chg",
+            "stakes",
+            "orphan%",
+            "missed",
+            "txs",
+            "immature%"
+        )?;
+        for cohort in &self.cohorts {
+            writeln!(
+                f,
+                "{:<20} {:>7} {:>14} {:>8} {:>12.4} {:>12.4} {:>8} {:>7.2}% {:>8} {:>8} {:>9.2}%",
+                format!("{:?}", cohort.key.denom_strat),
+                cohort.key.threshold_decile,
+                bucket_label(cohort.key.balance_bucket),
+                cohort.stakers,
+                cohort.mean_change_pct,
+                cohort.median_change_pct,
+                cohort.stake_count,
+                cohort.orphan_rate * 100.0,
+                cohort.missed_count,
+                cohort.transaction_count,
+                cohort.immature_pct * 100.0
+            )?;
+        }
+
+        if !self.downtime.is_empty() {
+            writeln!(f, "\nDowntime cost, always-on nodes against home nodes:")?;
+            writeln!(
+                f,
+                "{:>14} {:>10} {:>12} {:>8} {:>12} {:>9} {:>8}",
+                "balance", "always-on", "mean chg", "home", "mean chg", "uptime%", "missed"
+            )?;
+            for row in &self.downtime {
+                writeln!(
+                    f,
+                    "{:>14} {:>10} {:>12.4} {:>8} {:>12.4} {:>8.2}% {:>8}",
+                    bucket_label(row.balance_bucket),
+                    row.always_on_stakers,
+                    row.always_on_mean_change_pct,
+                    row.home_stakers,
+                    row.home_mean_change_pct,
+                    row.home_mean_uptime_pct * 100.0,
+                    row.home_missed_count
+                )?;
+            }
+        }
+
+        writeln!(f, "\nImmature share of balance over the run:")?;
+        writeln!(
+            f,
+            "{:>14} {:>8} {:>10} {:>10}",
+            "balance", "stakers", "mean%", "max%"
+        )?;
+        for row in &self.maturity {
+            writeln!(
+                f,
+                "{:>14} {:>8} {:>9.2}% {:>9.2}%",
+                bucket_label(row.balance_bucket),
+                row.stakers,
+                row.mean_immature_pct * 100.0,
+                row.max_immature_pct * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Decile, 0 through 9, of `threshold` within the sorted thresholds.
+fn threshold_decile(sorted: &[u64], threshold: u64) -> u8 {
+    let rank = sorted.partition_point(|p| *p < threshold);
+    (rank * 10 / sorted.len().max(1)).min(9) as u8
+}
+
+fn balance_bucket(balance: Amount) -> u8 {
+    BALANCE_BUCKETS
+        .iter()
+        .position(|p| balance < *p)
+        .unwrap_or(BALANCE_BUCKETS.len()) as u8
+}
+
+fn bucket_label(bucket: u8) -> String {
+    let bucket = bucket as usize;
+    let coins = |b: usize| BALANCE_BUCKETS[b].to_sat() / amount::COIN;
+    match bucket {
+        0 => format!("<{}", coins(0)),
+        b if b < BALANCE_BUCKETS.len() => format!("{}-{}", coins(b - 1), coins(b)),
+        _ => format!(">={}", coins(BALANCE_BUCKETS.len() - 1)),
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let len = sorted.len();
+    match len {
+        0 => 0.0,
+        _ if len.is_multiple_of(2) => (sorted[len / 2 - 1] + sorted[len / 2]) / 2.0,
+        _ => sorted[len / 2],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::availability::AvailabilityModel;
+    use crate::maturity::MaturitySchedule;
+    use crate::network::Network;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn test_threshold_decile() {
+        let sorted: Vec<u64> = (0..100).collect();
+        assert_eq!(threshold_decile(&sorted, 0), 0);
+        assert_eq!(threshold_decile(&sorted, 55), 5);
+        assert_eq!(threshold_decile(&sorted, 99), 9);
+    }
+
+    #[test]
+    fn test_balance_bucket() {
+        assert_eq!(balance_bucket(Amount::from_coins(999)), 0);
+        assert_eq!(balance_bucket(Amount::from_coins(1_000)), 1);
+        assert_eq!(balance_bucket(Amount::from_coins(5_000_000)), 4);
+        assert_eq!(bucket_label(4), ">=1000000");
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&[1.0, 2.0, 3.0]), 2.0);
+        assert_eq!(median(&[1.0, 2.0, 3.0, 4.0]), 2.5);
+    }
+
+    #[test]
+    fn test_longer_locks_keep_more_immature() {
+        let immature = |maturity: MaturitySchedule| -> f64 {
+            let mut rng = StdRng::seed_from_u64(6);
+            let mut network = Network::new();
+            network.set_maturity(maturity);
+            network.create_stakers(&mut rng).unwrap();
+            for _ in 0..300 {
+                network.step(&mut rng).unwrap();
+            }
+            let report = Report::from_stakers(network.stakers());
+            report.maturity().iter().map(|p| p.mean_immature_pct).sum()
+        };
+        let long = MaturitySchedule::new(vec!["0:500:20000:100".parse().unwrap()]);
+
+        assert!(immature(long) > immature(MaturitySchedule::default()));
+    }
+
+    #[test]
+    fn test_report_covers_every_staker() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut network = Network::new();
+        network.create_stakers(&mut rng).unwrap();
+        for _ in 0..200 {
+            network.step(&mut rng).unwrap();
+        }
+        network.update_stakers();
+
+        let report = Report::from_stakers(network.stakers());
+        let covered: usize = report.cohorts().iter().map(|p| p.stakers).sum();
+        let stakes: u64 = report.cohorts().iter().map(|p| p.stake_count).sum();
+        assert_eq!(covered, network.stakers().len());
+        assert!(stakes > 0);
+        assert!(report.downtime().is_empty());
+    }
+
+    #[test]
+    fn test_downtime_rows() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let mut network = Network::new();
+        network.create_stakers(&mut rng).unwrap();
+        network.set_availability(Some(AvailabilityModel {
+            failure_rate: 0.02,
+            recovery_rate: 0.02,
+            strength_scale: 1.0,
+        }));
+        for _ in 0..300 {
+            network.step(&mut rng).unwrap();
+        }
+        network.update_stakers();
+
+        let report = Report::from_stakers(network.stakers());
+        let rows: usize = report
+            .downtime()
+            .iter()
+            .map(|p| p.always_on_stakers + p.home_stakers)
+            .sum();
+        assert_eq!(rows, network.stakers().len());
+        assert!(report.downtime().iter().any(|p| p.home_stakers > 0));
+    }
+}
@@ -0,0 +1,634 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::adversary::Behavior;
+use crate::amount::Amount;
+use crate::availability::{AvailabilityModel, Uptime};
+use crate::capacity::CapacityModel;
+use crate::denom::{DENOM_THRESHOLD_MAX, DENOM_THRESHOLD_MIN, DenomStrategy};
+use crate::error::SimError;
+use crate::maturity::Maturity;
+use crate::pool::Pool;
+use crate::wallet::{Sent, Wallet};
+use crate::weight::{StakeMods, WeightRule};
+use crate::zerocoin::ZerocoinModel;
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Staker {
+    id: u64,
+    start_balance: Amount,
+    start_pct_total: f64,
+    balance_spendable: Amount,
+    balance_immature: Amount,
+    percent_total: f64,
+    change_pct: f64,
+    computer_strength: f64,
+    denom_strat: DenomStrategy,
+    denom_threshold: u64,
+    stake_count: u64,
+    orphaned_count: u64,
+    missed_count: u64,
+    uptime_pct: f64,
+    joined_height: u64,
+    left_height: Option<u64>,
+    uptime: Uptime,
+    behavior: Behavior,
+    rewards_earned: Amount,
+    fair_rewards: f64,
+    /// Heights and rewards of blocks found but not published yet.
+    withheld: Vec<(u64, Amount)>,
+    pool: Option<u64>,
+    staking: bool,
+    immature_pct_total: f64,
+    immature_samples: u64,
+    denoms: Wallet,
+    range: Range<f64>,
+}
+
+/// The per staker results written out at the end of a run, without the
+/// wallet contents.
+#[derive(Debug, Serialize)]
+pub struct StakerSummary {
+    id: u64,
+    start_balance: Amount,
+    start_pct_total: f64,
+    balance_spendable: Amount,
+    balance_immature: Amount,
+    percent_total: f64,
+    change_pct: f64,
+    computer_strength: f64,
+    denom_strat: DenomStrategy,
+    denom_threshold: u64,
+    stake_count: u64,
+    orphaned_count: u64,
+    missed_count: u64,
+    uptime_pct: f64,
+    joined_height: u64,
+    left_height: Option<u64>,
+    pool: Option<u64>,
+    transaction_count: u64,
+    mean_immature_pct: f64,
+}
+
+impl Staker {
+    pub fn try_new<R: Rng + ?Sized>(
+        balance: Amount,
+        id: u64,
+        start_pct_total: f64,
+        rng: &mut R,
+    ) -> Result<Self, SimError> {
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let denom_strat = rng.gen_range(0, 7);
+        let computer_strength = normal.sample(rng);
+
+        Self::try_with_params(
+            balance,
+            id,
+            start_pct_total,
+            DenomStrategy::from_index(denom_strat),
+            computer_strength,
+        )
+    }
+
+    /// Creates a staker with a chosen strategy and computer strength instead
+    /// of sampling them.
+    pub fn try_with_params(
+        balance: Amount,
+        id: u64,
+        start_pct_total: f64,
+        denom_strat: DenomStrategy,
+        computer_strength: f64,
+    ) -> Result<Self, SimError> {
+        let x: f64 = computer_strength;
+        let in_min: f64 = -5.0;
+        let in_max: f64 = 5.0;
+        let out_min: f64 = DENOM_THRESHOLD_MIN as f64;
+        let out_max: f64 = DENOM_THRESHOLD_MAX as f64;
+        let result: f64 = (x - in_min) * (out_max - out_min) / (in_max - in_min) + out_min;
+
+        let strat_index = DenomStrategy::ALL
+            .iter()
+            .position(|p| *p == denom_strat)
+            .unwrap();
+        let denoms = Wallet::try_init(balance, strat_index as u64, result as u64)?;
+        Ok(Self {
+            id,
+            denom_strat: denoms.strategy(),
+            denom_threshold: denoms.denom_threshold(),
+            denoms,
+            computer_strength,
+            start_balance: balance,
+            start_pct_total,
+            balance_spendable: balance,
+            balance_immature: Amount::ZERO,
+            percent_total: 0.0,
+            change_pct: 0.0,
+            stake_count: 0,
+            orphaned_count: 0,
+            missed_count: 0,
+            uptime_pct: 1.0,
+            joined_height: 0,
+            left_height: None,
+            uptime: Uptime::default(),
+            behavior: Behavior::default(),
+            rewards_earned: Amount::ZERO,
+            fair_rewards: 0.0,
+            withheld: Vec::new(),
+            pool: None,
+            staking: true,
+            immature_pct_total: 0.0,
+            immature_samples: 0,
+            range: Range {
+                start: 0.0,
+                end: 0.0,
+            },
+        })
+    }
+
+    pub fn summary(&self) -> StakerSummary {
+        StakerSummary {
+            id: self.id,
+            start_balance: self.start_balance,
+            start_pct_total: self.start_pct_total,
+            balance_spendable: self.balance_spendable,
+            balance_immature: self.balance_immature,
+            percent_total: self.percent_total,
+            change_pct: self.change_pct,
+            computer_strength: self.computer_strength,
+            denom_strat: self.denom_strat,
+            denom_threshold: self.denom_threshold,
+            stake_count: self.stake_count,
+            orphaned_count: self.orphaned_count,
+            missed_count: self.missed_count,
+            uptime_pct: self.uptime_pct,
+            joined_height: self.joined_height,
+            left_height: self.left_height,
+            pool: self.pool,
+            transaction_count: self.transaction_count(),
+            mean_immature_pct: self.mean_immature_pct(),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn start_balance(&self) -> Amount {
+        self.start_balance
+    }
+
+    pub fn balance_spendable(&self) -> Amount {
+        self.balance_spendable
+    }
+
+    pub fn balance_immature(&self) -> Amount {
+        self.balance_immature
+    }
+
+    pub fn percent_total(&self) -> f64 {
+        self.percent_total
+    }
+
+    pub fn change_pct(&self) -> f64 {
+        self.change_pct
+    }
+
+    pub fn computer_strength(&self) -> f64 {
+        self.computer_strength
+    }
+
+    pub fn denom_strat(&self) -> DenomStrategy {
+        self.denom_strat
+    }
+
+    pub fn denom_threshold(&self) -> u64 {
+        self.denom_threshold
+    }
+
+    pub fn stake_count(&self) -> u64 {
+        self.stake_count
+    }
+
+    /// Transfers the staker sent or received.
+    pub fn transaction_count(&self) -> u64 {
+        self.denoms.transaction_count()
+    }
+
+    pub fn orphaned_count(&self) -> u64 {
+        self.orphaned_count
+    }
+
+    /// Slots this staker won while its node was offline.
+    pub fn missed_count(&self) -> u64 {
+        self.missed_count
+    }
+
+    /// Share of blocks the node was online, as of the last `update`.
+    pub fn uptime_pct(&self) -> f64 {
+        self.uptime_pct
+    }
+
+    /// Delegated coins are staked by the pool operator, which is always on.
+    pub fn is_online(&self) -> bool {
+        self.pool.is_some() || self.uptime.is_online()
+    }
+
+    pub fn update_uptime<R: Rng + ?Sized>(
+        &mut self,
+        model: &AvailabilityModel,
+        block_height: u64,
+        rng: &mut R,
+    ) {
+        self.uptime
+            .update(model, self.computer_strength, block_height, rng);
+    }
+
+    /// Called when this staker found the kernel while its node was offline.
+    pub fn miss_stake(&mut self) {
+        self.missed_count += 1;
+    }
+
+    pub fn joined_height(&self) -> u64 {
+        self.joined_height
+    }
+
+    pub fn set_joined_height(&mut self, joined_height: u64) {
+        self.joined_height = joined_height;
+    }
+
+    pub fn left_height(&self) -> Option<u64> {
+        self.left_height
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.left_height.is_none()
+    }
+
+    /// Stops staking and moves the whole balance, immature outputs included,
+    /// off the staking set. Returns the value that left.
+    pub fn leave(&mut self, block_height: u64) -> Amount {
+        self.left_height = Some(block_height);
+        self.withheld.clear();
+        self.range = Range {
+            start: 0.0,
+            end: 0.0,
+        };
+        self.denoms.withdraw_all()
+    }
+
+    pub fn behavior(&self) -> Behavior {
+        self.behavior
+    }
+
+    pub fn set_behavior(&mut self, behavior: Behavior) {
+        self.behavior = behavior;
+    }
+
+    /// Rewards from blocks that made it into the chain.
+    pub fn rewards_earned(&self) -> Amount {
+        self.rewards_earned
+    }
+
+    /// Rewards the staker would have earned in proportion to its balance.
+    pub fn fair_rewards(&self) -> f64 {
+        self.fair_rewards
+    }
+
+    pub fn add_fair_reward(&mut self, reward: f64) {
+        self.fair_rewards += reward;
+    }
+
+    pub fn withheld(&self) -> &[(u64, Amount)] {
+        &self.withheld
+    }
+
+    pub fn withhold(&mut self, block_height: u64, reward: Amount) {
+        self.withheld.push((block_height, reward));
+    }
+
+    /// Publishes the oldest withheld block.
+    pub fn publish_one(&mut self) {
+        if !self.withheld.is_empty() {
+            self.withheld.remove(0);
+        }
+    }
+
+    pub fn publish_all(&mut self) {
+        self.withheld.clear();
+    }
+
+    /// The pool the staker delegates to, if any.
+    pub fn pool(&self) -> Option<u64> {
+        self.pool
+    }
+
+    pub fn set_pool(&mut self, pool: Option<u64>) {
+        self.pool = pool;
+    }
+
+    /// Whether the staker's coins take part in kernel searches at all.
+    pub fn is_staking(&self) -> bool {
+        self.staking && self.is_active()
+    }
+
+    pub fn set_staking(&mut self, staking: bool) {
+        self.staking = staking;
+    }
+
+    /// Sends up to `value` to another wallet.
+    pub fn send(
+        &mut self,
+        value: Amount,
+        block_height: u64,
+        maturity: &Maturity,
+    ) -> Result<Sent, SimError> {
+        self.denoms.send(value, block_height, maturity)
+    }
+
+    pub fn receive(
+        &mut self,
+        value: Amount,
+        block_height: u64,
+        maturity: &Maturity,
+    ) -> Result<(), SimError> {
+        self.denoms.receive(value, block_height, maturity)
+    }
+
+    pub fn denoms(&self) -> &Wallet {
+        &self.denoms
+    }
+
+    pub fn range(&self) -> &Range<f64> {
+        &self.range
+    }
+
+    pub fn set_range(&mut self, range: Range<f64>) {
+        self.range = range;
+    }
+
+    pub fn set_denom_strat(
+        &mut self,
+        denom_strat: DenomStrategy,
+        block_height: u64,
+    ) -> Result<(), SimError> {
+        self.denom_strat = denom_strat;
+        self.denoms.set_strategy(denom_strat, block_height)
+    }
+
+    pub fn set_weight_rule(&mut self, weight_rule: WeightRule) {
+        self.denoms.set_weight_rule(weight_rule);
+    }
+
+    pub fn set_stake_mods(&mut self, stake_mods: StakeMods) {
+        self.denoms.set_stake_mods(stake_mods);
+    }
+
+    pub fn set_zerocoin(
+        &mut self,
+        zerocoin: Option<ZerocoinModel>,
+        block_height: u64,
+    ) -> Result<(), SimError> {
+        self.denoms.set_zerocoin(zerocoin, block_height)
+    }
+
+    pub fn take_fees(&mut self) -> Amount {
+        self.denoms.take_fees()
+    }
+
+    pub fn migrate(
+        &mut self,
+        block_height: u64,
+        fee: Amount,
+        mature_height: u64,
+    ) -> Result<(), SimError> {
+        self.denoms.migrate(block_height, fee, mature_height)
+    }
+
+    pub fn reweigh(&mut self, block_height: u64) {
+        self.denoms.reweigh(block_height);
+    }
+
+    /// Called when this staker found the kernel. Returns false if the block
+    /// was orphaned because the wallet had more denoms than its computer could
+    /// hash in time, judged by the capacity model if there is one and by the
+    /// denom threshold otherwise. Delegated outputs are hashed by the
+    /// `operator`, which splits its computer over the whole pool.
+    pub fn hit_stake<R: Rng + ?Sized>(
+        &mut self,
+        block_height: u64,
+        reward: Amount,
+        capacity: Option<&CapacityModel>,
+        operator: Option<&Pool>,
+        maturity: &Maturity,
+        rng: &mut R,
+    ) -> Result<bool, SimError> {
+        let count = self.denoms.count();
+        let (computer_strength, threshold, load) = match operator {
+            Some(pool) => (
+                pool.computer_strength,
+                pool.denom_threshold,
+                pool.load().max(count),
+            ),
+            None => (self.computer_strength, self.denoms.denom_threshold(), count),
+        };
+
+        match capacity {
+            Some(model) => {
+                let budget = model.budget(computer_strength);
+                let budget = if load > count {
+                    (budget as u128 * count as u128 / load as u128) as u64
+                } else {
+                    budget
+                };
+                let coverage = self.denoms.coverage(budget);
+                if coverage < 1.0 && rng.gen::<f64>() >= coverage {
+                    self.orphaned_count += 1;
+                    return Ok(false);
+                }
+            }
+            None => {
+                if load > threshold {
+                    let res = rng.gen_range(0, load);
+                    if res > threshold {
+                        self.orphaned_count += 1;
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+
+        self.denoms.add_reward(reward, block_height, maturity)?;
+        self.stake_count += 1;
+        self.rewards_earned += reward;
+        self.denoms.lock_denom(block_height, maturity, rng)?;
+
+        Ok(true)
+    }
+
+    /// Called when the block this staker produced at `block_height` lost a
+    /// fork and was orphaned.
+    pub fn orphan_block(&mut self, block_height: u64, reward: Amount, maturity: &Maturity) {
+        self.denoms.reverse_stake(block_height, maturity);
+        self.stake_count -= 1;
+        self.orphaned_count += 1;
+        self.rewards_earned -= reward;
+        self.withheld.retain(|p| p.0 != block_height);
+    }
+
+    pub fn update(&mut self, total_supply: Amount, block_height: u64) {
+        self.uptime_pct = self.uptime.availability(block_height);
+        self.balance_spendable = self.denoms.mature_balance();
+        self.balance_immature = self.denoms.immature_balance();
+        self.percent_total = self.denoms.balance().to_sat() as f64 / total_supply.to_sat() as f64;
+        self.change_pct = self.percent_total / self.start_pct_total;
+    }
+
+    /// Records the share of the balance that is immature right now.
+    pub fn sample_immature(&mut self) {
+        let balance = self.denoms.balance();
+        if !balance.is_zero() {
+            self.immature_pct_total +=
+                self.denoms.immature_balance().to_sat() as f64 / balance.to_sat() as f64;
+        }
+        self.immature_samples += 1;
+    }
+
+    /// Average immature share of the balance over the samples taken.
+    pub fn mean_immature_pct(&self) -> f64 {
+        if self.immature_samples == 0 {
+            0.0
+        } else {
+            self.immature_pct_total / self.immature_samples as f64
+        }
+    }
+
+    pub fn are_stakes_maturing(&self) -> bool {
+        self.denoms.is_maturing()
+    }
+
+    /// Withheld rewards aren't on the public chain yet, so nothing matures
+    /// while the staker holds any back.
+    pub fn mature_balances(&mut self, block_height: u64) -> Result<(), SimError> {
+        if self.withheld.is_empty() {
+            self.denoms
+                .mature_outputs(block_height, self.behavior.consolidate())?;
+        }
+        Ok(())
+    }
+
+    pub fn stake_weight(&self) -> u64 {
+        self.denoms.weight()
+    }
+
+    pub fn stake_probability(&self, total_weight: u64) -> f64 {
+        self.denoms.stake_probability(total_weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn test_hit_stake_pays_reward() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let balance = Amount::from_coins(1_000);
+        let mut staker = Staker::try_new(balance, 0, 1.0, &mut rng).unwrap();
+        assert!(
+            staker
+                .hit_stake(
+                    10,
+                    Amount::from_coins(50),
+                    None,
+                    None,
+                    &Maturity::default(),
+                    &mut rng
+                )
+                .unwrap()
+        );
+
+        staker.update(balance, 10);
+        assert_eq!(staker.stake_count(), 1);
+        assert_eq!(
+            staker.balance_spendable() + staker.balance_immature(),
+            balance + Amount::from_coins(50)
+        );
+    }
+
+    #[test]
+    fn test_capacity_orphans_overloaded_wallets() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let balance = Amount::from_coins(100_000);
+        let mut staker =
+            Staker::try_with_params(balance, 0, 1.0, DenomStrategy::OnlySmall, 0.0).unwrap();
+        let model = CapacityModel {
+            hash_rate: 1.0,
+            strength_scale: 0.0,
+            search_interval: 100.0,
+        };
+
+        let found = (0..100)
+            .filter(|i| {
+                staker
+                    .hit_stake(
+                        *i,
+                        Amount::COIN,
+                        Some(&model),
+                        None,
+                        &Maturity::default(),
+                        &mut rng,
+                    )
+                    .unwrap()
+            })
+            .count() as u64;
+        assert_eq!(staker.orphaned_count(), 100 - found);
+        assert!(staker.orphaned_count() > 80);
+    }
+
+    #[test]
+    fn test_leave_empties_wallet() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let balance = Amount::from_coins(1_000);
+        let mut staker = Staker::try_new(balance, 0, 1.0, &mut rng).unwrap();
+        staker
+            .hit_stake(
+                10,
+                Amount::from_coins(50),
+                None,
+                None,
+                &Maturity::default(),
+                &mut rng,
+            )
+            .unwrap();
+
+        assert_eq!(staker.leave(20), balance + Amount::from_coins(50));
+        assert!(!staker.is_active());
+        assert_eq!(staker.stake_weight(), 0);
+    }
+}
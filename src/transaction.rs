@@ -0,0 +1,79 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+use crate::parse::number;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Everyday spending between stakers. Every block a Poisson number of
+/// transfers happens, each moving a random share of the sender's mature
+/// balance. Some go to or come from people outside the staking set.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct TransactionModel {
+    /// Expected transfers per block across the whole network.
+    pub rate: f64,
+    /// Largest share of the sender's mature balance a transfer moves.
+    pub max_fraction: f64,
+    /// Share of transfers with a non-staker, split evenly between paying
+    /// into the float and being paid from it.
+    pub external: f64,
+}
+
+/// Parses `RATE:MAX_FRACTION:EXTERNAL`.
+impl FromStr for TransactionModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<f64> = s.split(':').map(number).collect::<Result<_, _>>()?;
+        match parts.as_slice() {
+            [rate, max_fraction, external]
+                if *rate >= 0.0
+                    && (0.0..=1.0).contains(max_fraction)
+                    && (0.0..=1.0).contains(external) =>
+            {
+                Ok(Self {
+                    rate: *rate,
+                    max_fraction: *max_fraction,
+                    external: *external,
+                })
+            }
+            _ => Err(format!(
+                "Expected RATE:MAX_FRACTION:EXTERNAL with fractions between 0 and 1, got {}.",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_model() {
+        let model: TransactionModel = "5:0.1:0.2".parse().unwrap();
+        assert_eq!(model.rate, 5.0);
+        assert!("5:1.1:0.2".parse::<TransactionModel>().is_err());
+        assert!("5:0.1".parse::<TransactionModel>().is_err());
+    }
+}
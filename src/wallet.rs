@@ -1,8 +1,41 @@
-use crate::amount;
-use crate::denom::{self, Denom};
-use serde::Serialize;
+use crate::amount::Amount;
+use crate::denom::{self, Denom, DenomStrategy, DenomValue};
+use crate::error::SimError;
+use crate::maturity::Maturity;
+use crate::weight::{self, StakeMods, WeightFunction, WeightRule};
+use crate::zerocoin::ZerocoinModel;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, PartialEq)]
+/// Blocks a stake reward waits before it can be spent or staked.
+pub static STAKE_MATURITY: u64 = 30;
+/// Blocks a staked input stays locked before it can stake again.
+pub static INPUT_LOCK: u64 = 1_000;
+/// Blocks received coins and change wait before they can be staked.
+pub static TRANSFER_MATURITY: u64 = 100;
+
+/// When a wallet folds matured outputs back into denoms.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Consolidate {
+    /// Whenever anything matures, loose outputs only.
+    Always,
+    /// Only once a locked stake input comes back, together with everything
+    /// loose that piled up meanwhile.
+    OnUnlock,
+    /// Whenever anything matures, re-splitting every mature output.
+    Everything,
+}
+
+/// The result of `Wallet::send`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sent {
+    pub value: Amount,
+    pub inputs: u64,
+    /// The payment plus change, if any.
+    pub outputs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Wallet {
     initial_state: bool,
     denom_strat: u64,
@@ -10,14 +43,27 @@ pub struct Wallet {
     total_stake_count: u64,
     conf_stake_count: u64,
     transaction_count: u64,
+    weight_rule: WeightRule,
+    stake_mods: StakeMods,
+    /// Height the cached weight was computed at, for rules that age.
+    weight_height: u64,
+    weight: u64,
+    zerocoin: Option<ZerocoinModel>,
+    mint_count: u64,
+    mint_fees: Amount,
+    migration_fees: Amount,
+    /// Fees not yet handed to the network.
+    unpaid_fees: Amount,
     mature: Vec<Denom>,
     immature: Vec<Denom>,
 }
 
 impl Wallet {
-    fn init(balance: u64, denom_strat: u64, denom_threshold: u64) -> Self {
-        assert!(amount::money_range(balance));
-
+    pub fn try_init(
+        balance: Amount,
+        denom_strat: u64,
+        denom_threshold: u64,
+    ) -> Result<Self, SimError> {
         let mut wallet: Self = Wallet {
             initial_state: true,
             denom_strat,
@@ -25,292 +71,1032 @@ impl Wallet {
             total_stake_count: 0,
             conf_stake_count: 0,
             transaction_count: 0,
+            weight_rule: WeightRule::default(),
+            stake_mods: StakeMods::default(),
+            weight_height: 0,
+            weight: 0,
+            zerocoin: None,
+            mint_count: 0,
+            mint_fees: Amount::ZERO,
+            migration_fees: Amount::ZERO,
+            unpaid_fees: Amount::ZERO,
             mature: Vec::new(),
             immature: Vec::new(),
         };
-        // while bal_left >= 10 {
-        // let mut power = denom::MAX_POWER
-        // while balance != 0 {
-        //     if balance >= 2u64.pow(power) {
-        //     } else if balance < 2u64.pow(denom::MIN_POWER) {
-        //         else
-        //     } {
-        //
-        //         power -= 1;
-        //     }
-        // }
-        // if bal_left >= 1_000 {
-        //     wallet.mature.push(
-        //         Denom::builder()
-        //             .value(1_000 * amount::COIN)
-        //             .mature(true)
-        //             .build(),
-        //     );
-        //     bal_left -= 1_000;
-        // } else if bal_left >= 100 {
-        //     wallet.mature.push(
-        //         Denom::builder()
-        //             .value(100 * amount::COIN)
-        //             .mature(true)
-        //             .build(),
-        //     );
-        //     bal_left -= 100;
-        // } else if bal_left >= 10 {
-        //     wallet.mature.push(
-        //         Denom::builder()
-        //             .value(10 * amount::COIN)
-        //             .mature(true)
-        //             .build(),
-        //     );
-        //     bal_left -= 10;
-        // }
-        // }
-
-        // denoms.update_denoms(0);
-        //
+
+        let denoms = wallet.split(balance, 0)?;
+        wallet.push_split(denoms);
         wallet.initial_state = false;
 
-        wallet
+        Ok(wallet)
+    }
+
+    pub fn strategy(&self) -> DenomStrategy {
+        DenomStrategy::from_index(self.denom_strat)
+    }
+
+    /// Switches strategy and immediately re-splits every mature output
+    /// under the new one. Zerocoins left over from before RingCT are kept
+    /// until they are migrated.
+    pub fn set_strategy(
+        &mut self,
+        strategy: DenomStrategy,
+        block_height: u64,
+    ) -> Result<(), SimError> {
+        self.denom_strat = DenomStrategy::ALL
+            .iter()
+            .position(|p| *p == strategy)
+            .unwrap() as u64;
+
+        let legacy = self.zerocoin.is_none();
+        let (kept, spent): (Vec<Denom>, Vec<Denom>) = self
+            .mature
+            .drain(..)
+            .partition(|p| legacy && p.is_zerocoin());
+        self.mature = kept;
+        self.reweigh_all();
+        let value = spent.iter().map(Denom::value).sum();
+        let denoms = self.split(value, block_height)?;
+        self.push_split(denoms);
+        Ok(())
+    }
+
+    pub fn zerocoin(&self) -> Option<ZerocoinModel> {
+        self.zerocoin
+    }
+
+    /// Switches Zerocoin staking on or off. Turning it on re-splits mature
+    /// holdings as if they had been minted all along. Turning it off leaves
+    /// zerocoins unable to stake until `migrate` spends them.
+    pub fn set_zerocoin(
+        &mut self,
+        zerocoin: Option<ZerocoinModel>,
+        block_height: u64,
+    ) -> Result<(), SimError> {
+        self.zerocoin = zerocoin;
+        self.initial_state = true;
+        let strategy = self.strategy();
+        let result = self.set_strategy(strategy, block_height);
+        self.initial_state = false;
+        result
+    }
+
+    /// Zerocoins minted since the wallet was created, not counting the ones
+    /// it started out with.
+    pub fn mint_count(&self) -> u64 {
+        self.mint_count
+    }
+
+    pub fn mint_fees(&self) -> Amount {
+        self.mint_fees
+    }
+
+    pub fn migration_fees(&self) -> Amount {
+        self.migration_fees
+    }
+
+    /// Mint and migration fees paid since the last call, for the network to
+    /// pass on to block producers.
+    pub fn take_fees(&mut self) -> Amount {
+        std::mem::take(&mut self.unpaid_fees)
+    }
+
+    /// Fees paid but not yet taken by `take_fees`.
+    pub fn unpaid_fees(&self) -> Amount {
+        self.unpaid_fees
+    }
+
+    /// Whether the wallet still holds zerocoins after Zerocoin staking ended.
+    pub fn holds_zerocoins(&self) -> bool {
+        self.zerocoin.is_none()
+            && (self.mature.iter().any(Denom::is_zerocoin)
+                || self.immature.iter().any(Denom::is_zerocoin))
+    }
+
+    /// Spends every zerocoin, paying `fee` for each, into one RingCT output
+    /// that stakes from `mature_height`.
+    pub fn migrate(
+        &mut self,
+        block_height: u64,
+        fee: Amount,
+        mature_height: u64,
+    ) -> Result<(), SimError> {
+        let mut spent = Vec::new();
+        let mut i = 0;
+        while i < self.mature.len() {
+            if self.mature[i].is_zerocoin() {
+                spent.push(self.take_mature(i));
+            } else {
+                i += 1;
+            }
+        }
+        let (zerocoins, immature): (Vec<Denom>, Vec<Denom>) =
+            self.immature.drain(..).partition(Denom::is_zerocoin);
+        self.immature = immature;
+        spent.extend(zerocoins);
+        if spent.is_empty() {
+            return Ok(());
+        }
+
+        let mut value = Amount::ZERO;
+        for denom in &spent {
+            value = add(value, denom.value())?;
+        }
+        let inputs = spent.len() as u64;
+        let fee = fee
+            .checked_mul(inputs)
+            .ok_or_else(|| SimError::OutOfRange(fee.to_sat().saturating_mul(inputs)))?
+            .min(value);
+        self.migration_fees = add(self.migration_fees, fee)?;
+        self.unpaid_fees = add(self.unpaid_fees, fee)?;
+        self.transaction_count += 1;
+        let value = value - fee;
+        if value.is_zero() {
+            return Ok(());
+        }
+        if mature_height <= block_height {
+            let denom = self.new_mature(value, block_height);
+            self.push_mature(denom);
+            self.rebalance(block_height)
+        } else {
+            self.push_transfer(value, block_height, mature_height)
+        }
+    }
+
+    pub fn weight_rule(&self) -> WeightRule {
+        self.weight_rule
+    }
+
+    /// Changes the weight function and reweighs every output.
+    pub fn set_weight_rule(&mut self, weight_rule: WeightRule) {
+        self.weight_rule = weight_rule;
+        self.reweigh_all();
+    }
+
+    pub fn stake_mods(&self) -> &StakeMods {
+        &self.stake_mods
+    }
+
+    /// Changes the stake modifier rules and reweighs every output.
+    pub fn set_stake_mods(&mut self, stake_mods: StakeMods) {
+        self.stake_mods = stake_mods;
+        self.reweigh_all();
+    }
+
+    /// Brings the cached weight up to `block_height`. Only does any work
+    /// when the weight rule or a stake modifier ages outputs.
+    pub fn reweigh(&mut self, block_height: u64) {
+        let aged = self.weight_rule.is_aged() || self.stake_mods.is_aged();
+        if aged && self.weight_height != block_height {
+            self.weight_height = block_height;
+            self.reweigh_all();
+        }
+    }
+
+    pub fn denom_threshold(&self) -> u64 {
+        self.denom_threshold
+    }
+
+    pub fn total_stake_count(&self) -> u64 {
+        self.total_stake_count
+    }
+
+    pub fn conf_stake_count(&self) -> u64 {
+        self.conf_stake_count
+    }
+
+    pub fn transaction_count(&self) -> u64 {
+        self.transaction_count
+    }
+
+    pub fn mature(&self) -> &[Denom] {
+        &self.mature
     }
 
-    // fn ticket_count(&self) -> f64 {
-    //     let mut count = 0f64;
-    //
-    //     count += self.d10 as f64 * D10_MOD;
-    //     count += self.d100 as f64 * D100_MOD;
-    //     count += self.d1_000 as f64 * D1_000_MOD;
-    //     count += self.d10_000 as f64 * D10_000_MOD;
-    //
-    //     count
-    // }
-    //
-    // fn update_denoms(&mut self, block_height: u64) {
-    //     // 50/50 1000s, 10,000s
-    //     if self.denom_strat == 1 {
-    //         if self.d10 >= 10 {
-    //             self.d10 -= 10;
-    //             self.d100 += 1;
-    //         }
-    //
-    //         if self.d100 >= 10 {
-    //             self.d100 -= 10;
-    //             self.d1_000 += 1;
-    //         }
-    //
-    //         if self.d1_000 > 10 && self.d1_000 > self.d10_000 {
-    //             while self.d1_000 > self.d10_000 && self.d1_000 > 10 {
-    //                 self.d1_000 -= 10;
-    //                 self.d10_000 += 1;
-    //             }
-    //         }
-    //     }
-    //
-    //     // Equal across all denoms
-    //     if self.denom_strat == 2 {
-    //         if self.d10 > 10 && self.d10 > self.d100 {
-    //             while self.d10 > self.d100 && self.d10 > 10 {
-    //                 self.d10 -= 10;
-    //                 self.d100 += 1;
-    //             }
-    //         }
-    //
-    //         if self.d100 > 10 && self.d100 > self.d1_000 {
-    //             while self.d100 > self.d1_000 && self.d100 > 10 {
-    //                 self.d100 -= 10;
-    //                 self.d1_000 += 1;
-    //             }
-    //         }
-    //
-    //         if self.d1_000 > 10 && self.d1_000 > self.d10_000 {
-    //             while self.d1_000 > self.d10_000 && self.d1_000 > 10 {
-    //                 self.d1_000 -= 10;
-    //                 self.d10_000 += 1;
-    //             }
-    //         }
-    //     }
-    //
-    //     if self.denom_strat == 3 {
-    //         self.denoms_to_10_000();
-    //     }
-    //
-    //     // All 100s
-    //     if self.denom_strat == 4 && self.d10 > 10 && self.d10 > self.d100 {
-    //         while self.d10 > self.d100 && self.d10 > 10 {
-    //             self.d10 -= 10;
-    //             self.d100 += 1;
-    //         }
-    //     }
-    //
-    //     // All 100s and 1,000s 50/50
-    //     if self.denom_strat == 5 {
-    //         if self.d10 >= 10 {
-    //             self.d10 -= 10;
-    //             self.d100 += 1;
-    //         }
-    //
-    //         if self.d100 > 10 && self.d100 > self.d1_000 {
-    //             while self.d100 > self.d1_000 && self.d100 > 10 {
-    //                 self.d100 -= 10;
-    //                 self.d1_000 += 1;
-    //             }
-    //         }
-    //     }
-    //
-    //     // Move all to 10s then work up until you are at an optimal amount of denoms
-    //     if self.denom_strat == 6 {
-    //         if self.initial_state {
-    //             self.denoms_to_d10();
-    //         }
-    //
-    //         if self.count() > self.denom_threshold && self.d10 > 10 {
-    //             // delete
-    //             while self.d10 > 10 {
-    //                 self.d10 -= 10;
-    //                 if !self.initial_state {
-    //                     self.immature.push(ImmatureBalance {
-    //                         is_stake: false,
-    //                         reward: 100,
-    //                         height: block_height,
-    //                         mature_height: block_height + 1000,
-    //                     });
-    //                 } else {
-    //                     self.d100 += 1;
-    //                 }
-    //
-    //                 if self.count() < self.denom_threshold {
-    //                     break;
-    //                 }
-    //             }
-    //         }
-    //
-    //         if self.count() > self.denom_threshold && self.d100 > 10 {
-    //             while self.d100 > 10 {
-    //                 self.d100 -= 10;
-    //                 if !self.initial_state {
-    //                     self.immature.push(ImmatureBalance {
-    //                         is_stake: false,
-    //                         reward: 1_000,
-    //                         height: block_height,
-    //                         mature_height: block_height + 1000,
-    //                     });
-    //                     self.immature_stake_count += 1;
-    //                 } else {
-    //                     self.d1_000 += 1;
-    //                 }
-    //
-    //                 if self.count() < self.denom_threshold {
-    //                     break;
-    //                 }
-    //             }
-    //         }
-    //
-    //         if self.count() > self.denom_threshold && self.d1_000 > 10 {
-    //             while self.d1_000 > 10 {
-    //                 self.d1_000 -= 10;
-    //                 if !self.initial_state {
-    //                     self.immature.push(ImmatureBalance {
-    //                         is_stake: false,
-    //                         reward: 10_000,
-    //                         height: block_height,
-    //                         mature_height: block_height + 1000,
-    //                     });
-    //                 } else {
-    //                     self.d10_000 += 1;
-    //                 }
-    //
-    //                 if self.count() < self.denom_threshold {
-    //                     break;
-    //                 }
-    //             }
-    //         }
-    //     }
-    // }
-    //
-    // fn denoms_to_d10(&mut self) {
-    //     if self.d10_000 >= 1 {
-    //         self.d1_000 += self.d10_000 * 10;
-    //         self.d10_000 = 0;
-    //     }
-    //
-    //     if self.d1_000 >= 1 {
-    //         self.d100 += self.d1_000 * 10;
-    //         self.d1_000 = 0;
-    //     }
-    //
-    //     if self.d100 >= 1 {
-    //         self.d10 += self.d100 * 10;
-    //         self.d100 = 0;
-    //     }
-    // }
-    //
-    // fn denoms_to_10_000(&mut self) {
-    //     if self.d10 >= 10 {
-    //         self.d10 -= 10;
-    //         self.d100 += 1;
-    //     }
-    //
-    //     if self.d100 >= 10 {
-    //         self.d100 -= 10;
-    //         self.d1_000 += 1;
-    //     }
-    //
-    //     if self.d1_000 >= 10 {
-    //         self.d1_000 -= 10;
-    //         self.d10_000 += 1;
-    //     }
-    // }
-    //
-    // fn stake_probability(&self, total_supply: u64) -> f64 {
-    //     let adjusted_supply = total_supply / 10;
-    //     self.ticket_count() as f64 / adjusted_supply as f64
-    // }
-    //
-    // fn probability(&self, denom: &Denom) -> f64 {
-    //     use Denom::*;
-    //     match denom {
-    //         D10 => self.d10_probability(),
-    //         D100 => self.d100_probability(),
-    //         D1000 => self.d1_000_probability(),
-    //         D10000 => self.d10_000_probability(),
-    //     }
-    // }
-    //
-    // fn d10_probability(&self) -> f64 {
-    //     (self.d10 as f64 * D10_MOD) / self.ticket_count()
-    // }
-    //
-    // fn d100_probability(&self) -> f64 {
-    //     (self.d100 as f64 * D100_MOD) / self.ticket_count()
-    // }
-    //
-    // fn d1_000_probability(&self) -> f64 {
-    //     (self.d1_000 as f64 * D1_000_MOD) / self.ticket_count()
-    // }
-    //
-    // fn d10_000_probability(&self) -> f64 {
-    //     (self.d10_000 as f64 * D10_000_MOD) / self.ticket_count()
-    // }
-    //
-    // fn count(&self) -> u64 {
-    //     let mut count = 0;
-    //     count += self.d10;
-    //     count += self.d100;
-    //     count += self.d1_000;
-    //     count += self.d10_000;
-    //     count
-    // }
+    pub fn immature(&self) -> &[Denom] {
+        &self.immature
+    }
+
+    /// Number of mature outputs the wallet has to hash every kernel check.
+    pub fn count(&self) -> u64 {
+        self.mature.len() as u64
+    }
+
+    /// Total stake weight of the mature outputs.
+    pub fn weight(&self) -> u64 {
+        self.weight
+    }
+
+    pub fn mature_balance(&self) -> Amount {
+        self.mature.iter().map(Denom::value).sum()
+    }
+
+    pub fn immature_balance(&self) -> Amount {
+        self.immature.iter().map(Denom::value).sum()
+    }
+
+    pub fn balance(&self) -> Amount {
+        self.mature_balance() + self.immature_balance()
+    }
+
+    /// Share of the wallet's weight sitting in its `budget` heaviest outputs,
+    /// i.e. the chance a kernel is found on an output that was hashed in time.
+    pub fn coverage(&self, budget: u64) -> f64 {
+        if self.weight == 0 {
+            return 0.0;
+        }
+        if budget >= self.count() {
+            return 1.0;
+        }
+
+        let mut weights: Vec<u64> = self.mature.iter().map(|p| self.denom_weight(p)).collect();
+        weights.sort_unstable_by(|a, b| b.cmp(a));
+        let covered: u64 = weights.iter().take(budget as usize).sum();
+        covered as f64 / self.weight as f64
+    }
+
+    /// Chance of finding the next block, given the stake weight of every
+    /// wallet that is staking.
+    pub fn stake_probability(&self, total_weight: u64) -> f64 {
+        if total_weight == 0 {
+            return 0.0;
+        }
+        self.weight as f64 / total_weight as f64
+    }
+
+    /// Checks that the outputs agree with the wallet's own bookkeeping: every
+    /// output sits in the set its flag says, every unconfirmed stake still
+    /// has its reward maturing and the cached weight matches the outputs.
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(denom) = self.mature.iter().find(|p| !p.is_mature()) {
+            return Err(format!(
+                "immature output of {} in the mature set",
+                denom.value()
+            ));
+        }
+        if let Some(denom) = self.immature.iter().find(|p| p.is_mature()) {
+            return Err(format!(
+                "mature output of {} in the immature set",
+                denom.value()
+            ));
+        }
+
+        let maturing = self.immature.iter().filter(|p| p.is_stake()).count() as u64;
+        if self.conf_stake_count > self.total_stake_count
+            || self.total_stake_count - self.conf_stake_count != maturing
+        {
+            return Err(format!(
+                "{} stakes, {} confirmed, but {} rewards maturing",
+                self.total_stake_count, self.conf_stake_count, maturing
+            ));
+        }
+
+        let weight: u64 = self.mature.iter().map(|p| self.denom_weight(p)).sum();
+        if weight != self.weight {
+            return Err(format!(
+                "cached weight {} but outputs weigh {}",
+                self.weight, weight
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn is_maturing(&self) -> bool {
+        !self.immature.is_empty()
+    }
+
+    /// Adds a freshly staked reward, spendable once it is `maturity.stake`
+    /// blocks deep.
+    pub fn add_reward(
+        &mut self,
+        reward: Amount,
+        block_height: u64,
+        maturity: &Maturity,
+    ) -> Result<(), SimError> {
+        self.immature.push(
+            Denom::builder()
+                .value(reward)
+                .stake(true)
+                .created_height(block_height)
+                .mature_height(block_height + maturity.stake)
+                .build(),
+        );
+        self.total_stake_count += 1;
+        Ok(())
+    }
+
+    /// Picks the input that found the kernel, weighted by output weight, and
+    /// locks it for `maturity.input_lock` blocks, or only resets its age under
+    /// a weight rule that does that instead. Returns the staked value.
+    pub fn lock_denom<R: Rng + ?Sized>(
+        &mut self,
+        block_height: u64,
+        maturity: &Maturity,
+        rng: &mut R,
+    ) -> Result<Amount, SimError> {
+        let no_output = SimError::EmptySelection {
+            selection: "output to stake",
+            block_height,
+        };
+        if self.weight == 0 {
+            return Err(no_output);
+        }
+
+        let winning_weight = rng.gen_range(0, self.weight);
+        let mut start = 0;
+        let pos = self
+            .mature
+            .iter()
+            .position(|p| {
+                start += self.denom_weight(p);
+                winning_weight < start
+            })
+            .ok_or(no_output)?;
+
+        let denom = self.take_mature(pos);
+        let value = denom.value();
+        if self.weight_rule.resets_on_stake() {
+            // The output stakes again straight away, its age starting over.
+            self.push_mature(
+                Denom::builder()
+                    .value(value)
+                    .mature(true)
+                    .created_height(block_height)
+                    .mature_height(block_height)
+                    .zerocoin(denom.is_zerocoin())
+                    .build(),
+            );
+            return Ok(value);
+        }
+        self.immature.push(
+            Denom::builder()
+                .value(value)
+                .created_height(block_height)
+                .mature_height(self.input_mature_height(block_height, maturity))
+                .zerocoin(denom.is_zerocoin())
+                .build(),
+        );
+
+        Ok(value)
+    }
+
+    /// Undoes the stake made at `block_height` after its block lost a fork:
+    /// drops the reward and unlocks the staked input. An input whose age was
+    /// reset stays reset.
+    pub fn reverse_stake(&mut self, block_height: u64, maturity: &Maturity) {
+        if let Some(pos) = self
+            .immature
+            .iter()
+            .position(|p| p.is_stake() && p.created_height() == block_height)
+        {
+            self.immature.swap_remove(pos);
+            self.total_stake_count -= 1;
+        }
+
+        if let Some(pos) = self.immature.iter().position(|p| {
+            !p.is_stake()
+                && p.created_height() == block_height
+                && p.mature_height() == self.input_mature_height(block_height, maturity)
+        }) {
+            let mut denom = self.immature.swap_remove(pos);
+            denom.set_mature(true);
+            self.push_mature(denom);
+        }
+    }
+
+    /// Spends mature outputs worth at least `value` and keeps the change as a
+    /// new immature output. What was sent is less than `value` if the mature
+    /// balance doesn't cover it.
+    pub fn send(
+        &mut self,
+        value: Amount,
+        block_height: u64,
+        maturity: &Maturity,
+    ) -> Result<Sent, SimError> {
+        let mut spent = Amount::ZERO;
+        let mut inputs = 0;
+        while spent < value && !self.mature.is_empty() {
+            spent = add(spent, self.take_mature(self.mature.len() - 1).value())?;
+            inputs += 1;
+        }
+
+        let sent = spent.min(value);
+        if !sent.is_zero() {
+            self.transaction_count += 1;
+        }
+        let mut outputs = 1;
+        if spent > sent {
+            self.push_transfer(spent - sent, block_height, block_height + maturity.transfer)?;
+            outputs += 1;
+        }
+        Ok(Sent {
+            value: sent,
+            inputs,
+            outputs,
+        })
+    }
+
+    /// Adds an incoming output, stakeable after `maturity.transfer` blocks.
+    pub fn receive(
+        &mut self,
+        value: Amount,
+        block_height: u64,
+        maturity: &Maturity,
+    ) -> Result<(), SimError> {
+        self.push_transfer(value, block_height, block_height + maturity.transfer)?;
+        self.transaction_count += 1;
+        Ok(())
+    }
+
+    /// Empties the wallet, immature outputs included, and returns the value
+    /// that left it. Rewards still maturing leave with it, so their stakes
+    /// count as confirmed.
+    pub fn withdraw_all(&mut self) -> Amount {
+        let value = self.balance();
+        self.mature.clear();
+        self.immature.clear();
+        self.weight = 0;
+        self.conf_stake_count = self.total_stake_count;
+        value
+    }
+
+    /// Moves every output that reached its maturity height into the mature
+    /// set and lets the strategy fold any loose change back into denoms.
+    pub fn mature_outputs(
+        &mut self,
+        block_height: u64,
+        consolidate: Consolidate,
+    ) -> Result<(), SimError> {
+        let mut matured = false;
+        let mut unlocked = false;
+        let mut i = 0;
+        while i < self.immature.len() {
+            if self.immature[i].mature_height() <= block_height {
+                let mut denom = self.immature.swap_remove(i);
+                if denom.is_stake() {
+                    self.conf_stake_count += 1;
+                } else {
+                    unlocked = true;
+                }
+                denom.set_mature(true);
+                self.push_mature(denom);
+                matured = true;
+            } else {
+                i += 1;
+            }
+        }
+
+        match consolidate {
+            Consolidate::Always if matured => self.rebalance(block_height),
+            Consolidate::OnUnlock if unlocked => self.rebalance(block_height),
+            Consolidate::Everything if matured => {
+                let strategy = self.strategy();
+                self.set_strategy(strategy, block_height)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Merges every mature output that isn't a bracket target, or a zerocoin
+    /// under Zerocoin, and splits the sum again according to the strategy.
+    fn rebalance(&mut self, block_height: u64) -> Result<(), SimError> {
+        let mut loose = Amount::ZERO;
+        let mut i = 0;
+        while i < self.mature.len() {
+            if self.is_split(&self.mature[i]) {
+                i += 1;
+            } else {
+                loose = add(loose, self.take_mature(i).value())?;
+            }
+        }
+
+        let denoms = self.split(loose, block_height)?;
+        self.push_split(denoms);
+        Ok(())
+    }
+
+    /// Splits `value` into targets, topping up whichever bracket of the
+    /// strategy holds the least value. Whatever doesn't fit is returned as a
+    /// single change output. Under Zerocoin every target is a mint that pays
+    /// the mint fee out of `value`, unless the wallet is just being set up.
+    fn split(&mut self, value: Amount, block_height: u64) -> Result<Vec<Denom>, SimError> {
+        let brackets = match self.strategy() {
+            DenomStrategy::Optimal => vec![self.optimal_bracket(value)],
+            strategy => strategy.brackets().to_vec(),
+        };
+        let mut held: Vec<Amount> = brackets
+            .iter()
+            .map(|bracket| {
+                let target = self.target(*bracket);
+                self.mature
+                    .iter()
+                    .filter(|p| p.value() == target && self.is_split(p))
+                    .map(Denom::value)
+                    .sum()
+            })
+            .collect();
+        let fee = match self.zerocoin {
+            Some(model) if !self.initial_state => model.mint_fee,
+            _ => Amount::ZERO,
+        };
+
+        let mut denoms = Vec::new();
+        let mut left = value;
+        loop {
+            let next = brackets
+                .iter()
+                .enumerate()
+                .filter(|(_, bracket)| {
+                    self.target(**bracket)
+                        .checked_add(fee)
+                        .is_some_and(|cost| cost <= left)
+                })
+                .min_by_key(|(i, _)| held[*i]);
+            match next {
+                Some((i, bracket)) => {
+                    let target = self.target(*bracket);
+                    denoms.push(self.new_target(target, block_height));
+                    held[i] = add(held[i], target)?;
+                    left -= target + fee;
+                    if self.zerocoin.is_some() && !self.initial_state {
+                        self.mint_count += 1;
+                        self.mint_fees = add(self.mint_fees, fee)?;
+                        self.unpaid_fees = add(self.unpaid_fees, fee)?;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        if !left.is_zero() {
+            denoms.push(self.new_mature(left, block_height));
+        }
+
+        Ok(denoms)
+    }
+
+    /// Smallest bracket that keeps the wallet at or below its denom threshold.
+    fn optimal_bracket(&self, value: Amount) -> u32 {
+        let balance = self.mature_balance().saturating_add(value).to_sat();
+        let count = self.target_count();
+        (0..count)
+            .find(|bracket| balance / self.target(*bracket).to_sat() <= self.denom_threshold)
+            .unwrap_or(count - 1)
+    }
+
+    /// Value of the outputs a strategy bracket is split into: the bracket
+    /// target, or under Zerocoin the matching denomination.
+    fn target(&self, bracket: u32) -> Amount {
+        match self.zerocoin {
+            Some(_) => DenomValue::ALL[bracket as usize].value(),
+            None => Amount::from_sat(denom::bracket_target(bracket)),
+        }
+    }
+
+    fn target_count(&self) -> u32 {
+        match self.zerocoin {
+            Some(_) => DenomValue::ALL.len() as u32,
+            None => denom::bracket_count(),
+        }
+    }
+
+    /// Whether an output is one the strategy split off, rather than loose
+    /// change waiting to be merged. Zerocoins waiting to be migrated are
+    /// never merged.
+    fn is_split(&self, denom: &Denom) -> bool {
+        match self.zerocoin {
+            Some(_) => denom.is_zerocoin(),
+            None => denom.is_target() || denom.is_zerocoin(),
+        }
+    }
+
+    /// A split off output. Under Zerocoin it's a mint that waits for an
+    /// accumulator checkpoint, or one the wallet already held when set up.
+    fn new_target(&self, value: Amount, block_height: u64) -> Denom {
+        match self.zerocoin {
+            Some(model) if !self.initial_state => Denom::builder()
+                .value(value)
+                .created_height(block_height)
+                .mature_height(model.mint_maturity(block_height))
+                .zerocoin(true)
+                .build(),
+            Some(_) => Denom::builder()
+                .value(value)
+                .mature(true)
+                .created_height(block_height)
+                .mature_height(block_height)
+                .zerocoin(true)
+                .build(),
+            None => self.new_mature(value, block_height),
+        }
+    }
+
+    fn new_mature(&self, value: Amount, block_height: u64) -> Denom {
+        Denom::builder()
+            .value(value)
+            .mature(true)
+            .created_height(block_height)
+            .mature_height(block_height)
+            .build()
+    }
+
+    /// Height a staked input unlocks at. A staked zerocoin is reminted, so it
+    /// also waits for an accumulator checkpoint.
+    fn input_mature_height(&self, block_height: u64, maturity: &Maturity) -> u64 {
+        let unlocked = block_height + maturity.input_lock;
+        match self.zerocoin {
+            Some(model) => unlocked.max(model.mint_maturity(block_height)),
+            None => unlocked,
+        }
+    }
+
+    fn push_split(&mut self, denoms: Vec<Denom>) {
+        for denom in denoms {
+            if denom.is_mature() {
+                self.push_mature(denom);
+            } else {
+                self.immature.push(denom);
+            }
+        }
+    }
+
+    fn push_mature(&mut self, mut denom: Denom) {
+        denom.set_stake_mod(self.stake_mods.stake_mod(&denom, self.weight_height));
+        self.weight += self.denom_weight(&denom);
+        self.mature.push(denom);
+    }
+
+    fn take_mature(&mut self, pos: usize) -> Denom {
+        let denom = self.mature.swap_remove(pos);
+        self.weight -= self.denom_weight(&denom);
+        denom
+    }
+
+    /// Weight of a mature output, scaled by the stake modifier it was last
+    /// given. Only zerocoins stake under Zerocoin, and only RingCT outputs
+    /// after it.
+    fn denom_weight(&self, denom: &Denom) -> u64 {
+        if self.zerocoin.is_some() != denom.is_zerocoin() {
+            return 0;
+        }
+        weight::modified_weight(
+            self.weight_rule.weight(denom, self.weight_height),
+            denom.stake_mod(),
+        )
+    }
+
+    fn reweigh_all(&mut self) {
+        let block_height = self.weight_height;
+        for denom in &mut self.mature {
+            denom.set_stake_mod(self.stake_mods.stake_mod(denom, block_height));
+        }
+        self.weight = self.mature.iter().map(|p| self.denom_weight(p)).sum();
+    }
+
+    /// Adds an output that matures at `mature_height`, as long as the wallet
+    /// stays within the money range.
+    fn push_transfer(
+        &mut self,
+        value: Amount,
+        block_height: u64,
+        mature_height: u64,
+    ) -> Result<(), SimError> {
+        add(self.balance(), value)?;
+        self.immature.push(
+            Denom::builder()
+                .value(value)
+                .created_height(block_height)
+                .mature_height(mature_height)
+                .build(),
+        );
+        Ok(())
+    }
+}
+
+/// `a + b`, or `OutOfRange` if the sum leaves the money range.
+fn add(a: Amount, b: Amount) -> Result<Amount, SimError> {
+    a.checked_add(b)
+        .ok_or_else(|| SimError::OutOfRange(a.to_sat().saturating_add(b.to_sat())))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
 
     #[test]
     fn test_init_wallet() {
         println!("starting");
-        let balance = 1_234_567 * amount::COIN;
-        let wallet = Wallet::init(balance, 0, 0);
+        let balance = Amount::from_coins(1_234_567);
+        let wallet = Wallet::try_init(balance, 0, 0).unwrap();
         println!("mature: {:#?}", wallet.mature.len());
+        assert_eq!(wallet.mature_balance(), balance);
+    }
+
+    #[test]
+    fn test_init_half_strategy() {
+        let balance = Amount::from_coins(10_000);
+        let wallet = Wallet::try_init(balance, 4, 0).unwrap();
+        let small: Amount = wallet
+            .mature
+            .iter()
+            .filter(|p| p.value() == Amount::from_sat(denom::bracket_target(0)))
+            .map(Denom::value)
+            .sum();
+        let medium: Amount = wallet
+            .mature
+            .iter()
+            .filter(|p| p.value() == Amount::from_sat(denom::bracket_target(1)))
+            .map(Denom::value)
+            .sum();
+        assert!(
+            small.max(medium) - small.min(medium) <= Amount::from_sat(denom::bracket_target(1))
+        );
+    }
+
+    #[test]
+    fn test_optimal_respects_threshold() {
+        let balance = Amount::from_coins(100_000);
+        let wallet = Wallet::try_init(balance, 8, 100).unwrap();
+        assert!(wallet.count() <= 101);
+        assert_eq!(wallet.mature_balance(), balance);
+    }
+
+    #[test]
+    fn test_lock_and_mature() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let balance = Amount::from_coins(5_000);
+        let mut wallet = Wallet::try_init(balance, 1, 0).unwrap();
+        let weight = wallet.weight();
+
+        wallet
+            .lock_denom(100, &Maturity::default(), &mut rng)
+            .unwrap();
+        wallet
+            .add_reward(Amount::from_coins(50), 100, &Maturity::default())
+            .unwrap();
+        assert!(wallet.weight() < weight);
+        assert_eq!(wallet.balance(), balance + Amount::from_coins(50));
+
+        wallet
+            .mature_outputs(100 + INPUT_LOCK, Consolidate::Always)
+            .unwrap();
+        assert!(!wallet.is_maturing());
+        assert_eq!(wallet.conf_stake_count(), 1);
+        assert_eq!(wallet.mature_balance(), balance + Amount::from_coins(50));
+        let weight: u64 = wallet.mature().iter().map(Denom::weight).sum();
+        assert_eq!(wallet.weight(), weight);
+    }
+
+    #[test]
+    fn test_lock_empty_wallet_fails() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut wallet = Wallet::try_init(Amount::ZERO, 1, 0).unwrap();
+        assert!(matches!(
+            wallet.lock_denom(100, &Maturity::default(), &mut rng),
+            Err(SimError::EmptySelection {
+                block_height: 100,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_set_strategy_resplits() {
+        let balance = Amount::from_coins(20_000);
+        let mut wallet = Wallet::try_init(balance, 0, 0).unwrap();
+        let count = wallet.count();
+
+        wallet.set_strategy(DenomStrategy::OnlyHuge, 10).unwrap();
+        assert_eq!(wallet.strategy(), DenomStrategy::OnlyHuge);
+        assert!(wallet.count() < count);
+        assert_eq!(wallet.mature_balance(), balance);
+    }
+
+    #[test]
+    fn test_coin_days_stake_resets_age() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let balance = Amount::from_coins(5_000);
+        let mut wallet = Wallet::try_init(balance, 3, 0).unwrap();
+        wallet.set_weight_rule("coin-days:100:1_000".parse().unwrap());
+        wallet.reweigh(2_000);
+        assert_eq!(wallet.weight(), balance.to_sat());
+
+        let value = wallet
+            .lock_denom(2_000, &Maturity::default(), &mut rng)
+            .unwrap();
+        assert!(!wallet.is_maturing());
+        assert_eq!(wallet.mature_balance(), balance);
+        assert_eq!(wallet.weight(), (balance - value).to_sat());
+        wallet.reweigh(2_550);
+        assert_eq!(wallet.weight(), (balance - value + value / 2).to_sat());
+    }
+
+    #[test]
+    fn test_zerocoin_mints_pay_fee_and_wait() {
+        let model = ZerocoinModel::default();
+        let balance = Amount::from_coins(5_555);
+        let reward = Amount::from_coins(50);
+        let mut wallet = Wallet::try_init(balance, 7, 0).unwrap();
+        wallet.set_weight_rule("linear".parse().unwrap());
+        wallet.set_zerocoin(Some(model), 0).unwrap();
+        assert_eq!(wallet.mint_count(), 0);
+        assert_eq!(wallet.mature_balance(), balance);
+        assert!(
+            wallet
+                .mature()
+                .iter()
+                .filter(|p| p.is_zerocoin())
+                .all(|p| DenomValue::ALL.iter().any(|d| d.value() == p.value()))
+        );
+
+        wallet
+            .add_reward(reward, 100, &Maturity::default())
+            .unwrap();
+        wallet
+            .mature_outputs(100 + STAKE_MATURITY, Consolidate::Always)
+            .unwrap();
+        let fees = wallet.take_fees();
+        assert!(wallet.mint_count() > 0);
+        assert_eq!(fees, model.mint_fee * wallet.mint_count());
+        assert_eq!(wallet.take_fees(), Amount::ZERO);
+        assert_eq!(wallet.balance() + fees, balance + reward);
+
+        let weight = wallet.weight();
+        assert!(wallet.is_maturing());
+        wallet
+            .mature_outputs(
+                model.mint_maturity(100 + STAKE_MATURITY),
+                Consolidate::Always,
+            )
+            .unwrap();
+        assert!(!wallet.is_maturing());
+        assert!(wallet.weight() > weight);
+    }
+
+    #[test]
+    fn test_migrate_zerocoins_to_ringct() {
+        let fee = Amount::COIN / 100;
+        let balance = Amount::from_coins(5_500);
+        let mut wallet = Wallet::try_init(balance, 1, 0).unwrap();
+        wallet
+            .set_zerocoin(Some(ZerocoinModel::default()), 0)
+            .unwrap();
+        assert_eq!(wallet.count(), 55);
+
+        wallet.set_zerocoin(None, 10).unwrap();
+        assert!(wallet.holds_zerocoins());
+        assert_eq!(wallet.weight(), 0);
+        assert_eq!(wallet.mature_balance(), balance);
+
+        wallet.migrate(10, fee, 110).unwrap();
+        assert!(!wallet.holds_zerocoins());
+        assert_eq!(wallet.take_fees(), fee * 55);
+        assert_eq!(wallet.balance(), balance - fee * 55);
+        wallet.mature_outputs(110, Consolidate::Always).unwrap();
+        assert!(wallet.weight() > 0);
+        assert!(wallet.mature().iter().all(|p| !p.is_zerocoin()));
+    }
+
+    #[test]
+    fn test_out_of_range_is_an_error() {
+        let mut wallet = Wallet::try_init(Amount::MAX_MONEY, 1, 0).unwrap();
+        assert!(matches!(
+            wallet.receive(Amount::COIN, 10, &Maturity::default()),
+            Err(SimError::OutOfRange(_))
+        ));
+        assert_eq!(wallet.balance(), Amount::MAX_MONEY);
+
+        let mut wallet = Wallet::try_init(Amount::from_coins(5_500), 1, 0).unwrap();
+        wallet
+            .set_zerocoin(Some(ZerocoinModel::default()), 0)
+            .unwrap();
+        wallet.set_zerocoin(None, 10).unwrap();
+        assert!(matches!(
+            wallet.migrate(10, Amount::MAX_MONEY, 110),
+            Err(SimError::OutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_reverse_stake() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let balance = Amount::from_coins(5_000);
+        let mut wallet = Wallet::try_init(balance, 2, 0).unwrap();
+        let weight = wallet.weight();
+
+        wallet
+            .add_reward(Amount::from_coins(50), 100, &Maturity::default())
+            .unwrap();
+        wallet
+            .lock_denom(100, &Maturity::default(), &mut rng)
+            .unwrap();
+        wallet.reverse_stake(100, &Maturity::default());
+
+        assert!(!wallet.is_maturing());
+        assert_eq!(wallet.total_stake_count(), 0);
+        assert_eq!(wallet.weight(), weight);
+        assert_eq!(wallet.balance(), balance);
+    }
+
+    #[test]
+    fn test_consolidate_on_unlock_defers() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let balance = Amount::from_coins(5_000);
+        let mut wallet = Wallet::try_init(balance, 1, 0).unwrap();
+        wallet
+            .add_reward(Amount::from_coins(50), 100, &Maturity::default())
+            .unwrap();
+        wallet
+            .lock_denom(100, &Maturity::default(), &mut rng)
+            .unwrap();
+
+        wallet
+            .mature_outputs(100 + STAKE_MATURITY, Consolidate::OnUnlock)
+            .unwrap();
+        assert!(
+            wallet
+                .mature()
+                .iter()
+                .any(|p| p.value() == Amount::from_coins(50))
+        );
+
+        wallet
+            .mature_outputs(100 + INPUT_LOCK, Consolidate::OnUnlock)
+            .unwrap();
+        assert!(
+            wallet
+                .mature()
+                .iter()
+                .all(|p| p.value() != Amount::from_coins(50))
+        );
+    }
+
+    #[test]
+    fn test_send_and_receive() {
+        let balance = Amount::from_coins(5_000);
+        let mut wallet = Wallet::try_init(balance, 1, 0).unwrap();
+        let count = wallet.count();
+
+        let sent = wallet
+            .send(Amount::from_coins(1_234), 10, &Maturity::default())
+            .unwrap();
+        assert_eq!(sent.value, Amount::from_coins(1_234));
+        assert_eq!(sent.outputs, 2);
+        assert!(wallet.count() < count);
+        assert_eq!(wallet.balance(), balance - Amount::from_coins(1_234));
+        let mature = wallet.mature_balance();
+        assert_eq!(
+            wallet
+                .send(balance, 10, &Maturity::default())
+                .unwrap()
+                .value,
+            mature
+        );
+        assert_eq!(wallet.weight(), 0);
+
+        let mut other = Wallet::try_init(Amount::ZERO, 1, 0).unwrap();
+        other
+            .receive(Amount::COIN, 10, &Maturity::default())
+            .unwrap();
+        assert_eq!(other.weight(), 0);
+        assert_eq!(wallet.transaction_count(), 2);
+        assert_eq!(other.transaction_count(), 1);
+        other
+            .mature_outputs(10 + TRANSFER_MATURITY, Consolidate::Always)
+            .unwrap();
+        assert_eq!(other.mature_balance(), Amount::COIN);
+    }
+
+    #[test]
+    fn test_withdraw_all_confirms_maturing_stakes() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let balance = Amount::from_coins(5_000);
+        let mut wallet = Wallet::try_init(balance, 1, 0).unwrap();
+        wallet
+            .lock_denom(100, &Maturity::default(), &mut rng)
+            .unwrap();
+        wallet
+            .add_reward(Amount::from_coins(50), 100, &Maturity::default())
+            .unwrap();
+        assert_eq!(wallet.conf_stake_count(), 0);
+
+        assert_eq!(wallet.withdraw_all(), balance + Amount::from_coins(50));
+        assert_eq!(wallet.balance(), Amount::ZERO);
+        assert_eq!(wallet.weight(), 0);
+        assert_eq!(wallet.total_stake_count(), 1);
+        assert_eq!(wallet.conf_stake_count(), 1);
+        assert_eq!(wallet.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_coverage() {
+        let balance = Amount::from_coins(10_000);
+        let wallet = Wallet::try_init(balance, 0, 0).unwrap();
+        assert_eq!(wallet.coverage(wallet.count()), 1.0);
+        assert_eq!(wallet.coverage(0), 0.0);
+        let half = wallet.coverage(wallet.count() / 2);
+        assert!(half > 0.4 && half < 0.6);
     }
 
     #[test]
@@ -0,0 +1,444 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::amount::Amount;
+use crate::denom::{self, Denom};
+use crate::error::SimError;
+use crate::parse::number;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Turns a mature output into the weight it stakes with.
+pub trait WeightFunction {
+    fn weight(&self, denom: &Denom, block_height: u64) -> u64;
+
+    /// Whether weights change as outputs age, so a cached total goes stale
+    /// every block.
+    fn is_aged(&self) -> bool {
+        false
+    }
+
+    /// Whether a staked output goes straight back to staking with its age
+    /// reset, rather than being locked for `Maturity::input_lock` blocks.
+    fn resets_on_stake(&self) -> bool {
+        false
+    }
+}
+
+/// The consensus rule: the bottom of the output's 4× bracket, less
+/// `bracket_mod` for every bracket above the first.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct BracketWeight {
+    pub bracket_mod: f64,
+}
+
+impl Default for BracketWeight {
+    fn default() -> Self {
+        Self {
+            bracket_mod: denom::DENOM_BRACKET_MOD,
+        }
+    }
+}
+
+impl WeightFunction for BracketWeight {
+    fn weight(&self, denom: &Denom, _block_height: u64) -> u64 {
+        denom.bracket_weight(self.bracket_mod)
+    }
+}
+
+/// Weight equal to value.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct LinearWeight;
+
+impl WeightFunction for LinearWeight {
+    fn weight(&self, denom: &Denom, _block_height: u64) -> u64 {
+        denom.value().to_sat()
+    }
+}
+
+/// Linear up to `DENOM_MIN`, logarithmic above it, so splitting a balance
+/// into more outputs always adds weight.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct LogWeight;
+
+impl WeightFunction for LogWeight {
+    fn weight(&self, denom: &Denom, _block_height: u64) -> u64 {
+        let value = denom.value().to_sat();
+        if value <= denom::DENOM_MIN {
+            return value;
+        }
+        let scale = denom::DENOM_MIN as f64;
+        (scale * (1.0 + (value as f64 / scale).ln())) as u64
+    }
+}
+
+/// Weight equal to value, but never more than `cap`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct CappedWeight {
+    pub cap: u64,
+}
+
+impl WeightFunction for CappedWeight {
+    fn weight(&self, denom: &Denom, _block_height: u64) -> u64 {
+        denom.value().to_sat().min(self.cap)
+    }
+}
+
+/// The bracket weight under `base` scaled by the output's age, reaching full
+/// weight after `max_age` blocks.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct CoinAgeWeight {
+    pub max_age: u64,
+    #[serde(default)]
+    pub base: BracketWeight,
+}
+
+impl WeightFunction for CoinAgeWeight {
+    fn weight(&self, denom: &Denom, block_height: u64) -> u64 {
+        let max_age = self.max_age.max(1);
+        let age = block_height
+            .saturating_sub(denom.created_height())
+            .clamp(1, max_age);
+        let weight = self.base.weight(denom, block_height);
+        (weight as u128 * age as u128 / max_age as u128) as u64
+    }
+
+    fn is_aged(&self) -> bool {
+        true
+    }
+}
+
+/// Classic coin-age proof of stake, as in Peercoin: an output weighs its value
+/// times its age past `min_age`, counting age up to `max_age`, and staking it
+/// resets its age. Scaled so a fully aged output weighs its value.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct CoinDaysWeight {
+    pub min_age: u64,
+    pub max_age: u64,
+}
+
+impl WeightFunction for CoinDaysWeight {
+    fn weight(&self, denom: &Denom, block_height: u64) -> u64 {
+        let age = block_height.saturating_sub(denom.created_height());
+        let aged = age.min(self.max_age).saturating_sub(self.min_age);
+        let span = self.max_age.saturating_sub(self.min_age).max(1);
+        (denom.value().to_sat() as u128 * aged as u128 / span as u128) as u64
+    }
+
+    fn is_aged(&self) -> bool {
+        true
+    }
+
+    fn resets_on_stake(&self) -> bool {
+        true
+    }
+}
+
+/// The weight function a scenario runs with.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum WeightRule {
+    Bracket(BracketWeight),
+    Linear(LinearWeight),
+    Log(LogWeight),
+    Capped(CappedWeight),
+    CoinAge(CoinAgeWeight),
+    CoinDays(CoinDaysWeight),
+}
+
+impl Default for WeightRule {
+    fn default() -> Self {
+        WeightRule::Bracket(BracketWeight::default())
+    }
+}
+
+impl WeightRule {
+    /// The rule with `bracket_mod` as its bracket penalty. Only rules built
+    /// on the bracket weight have one.
+    pub fn with_bracket_mod(self, bracket_mod: f64) -> Result<Self, SimError> {
+        let base = BracketWeight { bracket_mod };
+        match self {
+            WeightRule::Bracket(_) => Ok(WeightRule::Bracket(base)),
+            WeightRule::CoinAge(rule) => Ok(WeightRule::CoinAge(CoinAgeWeight { base, ..rule })),
+            _ => Err(SimError::InvalidConfig(
+                "Only the bracket and coin-age weight rules have a bracket penalty.".to_string(),
+            )),
+        }
+    }
+}
+
+impl WeightFunction for WeightRule {
+    fn weight(&self, denom: &Denom, block_height: u64) -> u64 {
+        match self {
+            WeightRule::Bracket(rule) => rule.weight(denom, block_height),
+            WeightRule::Linear(rule) => rule.weight(denom, block_height),
+            WeightRule::Log(rule) => rule.weight(denom, block_height),
+            WeightRule::Capped(rule) => rule.weight(denom, block_height),
+            WeightRule::CoinAge(rule) => rule.weight(denom, block_height),
+            WeightRule::CoinDays(rule) => rule.weight(denom, block_height),
+        }
+    }
+
+    fn is_aged(&self) -> bool {
+        match self {
+            WeightRule::Bracket(rule) => rule.is_aged(),
+            WeightRule::Linear(rule) => rule.is_aged(),
+            WeightRule::Log(rule) => rule.is_aged(),
+            WeightRule::Capped(rule) => rule.is_aged(),
+            WeightRule::CoinAge(rule) => rule.is_aged(),
+            WeightRule::CoinDays(rule) => rule.is_aged(),
+        }
+    }
+
+    fn resets_on_stake(&self) -> bool {
+        match self {
+            WeightRule::Bracket(rule) => rule.resets_on_stake(),
+            WeightRule::Linear(rule) => rule.resets_on_stake(),
+            WeightRule::Log(rule) => rule.resets_on_stake(),
+            WeightRule::Capped(rule) => rule.resets_on_stake(),
+            WeightRule::CoinAge(rule) => rule.resets_on_stake(),
+            WeightRule::CoinDays(rule) => rule.resets_on_stake(),
+        }
+    }
+}
+
+/// Parses `bracket[:MOD]`, `linear`, `log`, `capped:COINS` or
+/// `coin-age:MAX_AGE[:MOD]` or `coin-days:MIN_AGE:MAX_AGE`.
+impl FromStr for WeightRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts.as_slice() {
+            ["bracket"] => Ok(WeightRule::Bracket(BracketWeight::default())),
+            ["bracket", bracket_mod] => Ok(WeightRule::Bracket(BracketWeight {
+                bracket_mod: number(bracket_mod)?,
+            })),
+            ["linear"] => Ok(WeightRule::Linear(LinearWeight)),
+            ["log"] => Ok(WeightRule::Log(LogWeight)),
+            ["capped", coins] => Ok(WeightRule::Capped(CappedWeight {
+                cap: number::<Amount>(coins)?.to_sat(),
+            })),
+            ["coin-age", max_age] => Ok(WeightRule::CoinAge(CoinAgeWeight {
+                max_age: number(max_age)?,
+                base: BracketWeight::default(),
+            })),
+            ["coin-age", max_age, bracket_mod] => Ok(WeightRule::CoinAge(CoinAgeWeight {
+                max_age: number(max_age)?,
+                base: BracketWeight {
+                    bracket_mod: number(bracket_mod)?,
+                },
+            })),
+            ["coin-days", min_age, max_age] => Ok(WeightRule::CoinDays(CoinDaysWeight {
+                min_age: number(min_age)?,
+                max_age: number(max_age)?,
+            })),
+            _ => Err(format!("Unknown weight rule {}.", s)),
+        }
+    }
+}
+
+/// A multiplier on the weight of the outputs it matches.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum StakeModRule {
+    /// Outputs created less than `age` blocks ago.
+    Young { age: u64, multiplier: f64 },
+    /// Outputs minted by a stake reward.
+    Reward { multiplier: f64 },
+    /// Every output.
+    All { multiplier: f64 },
+}
+
+impl StakeModRule {
+    fn multiplier(&self, denom: &Denom, block_height: u64) -> f64 {
+        match *self {
+            StakeModRule::Young { age, multiplier }
+                if block_height.saturating_sub(denom.created_height()) < age =>
+            {
+                multiplier
+            }
+            StakeModRule::Reward { multiplier } if denom.is_stake() => multiplier,
+            StakeModRule::All { multiplier } => multiplier,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Parses `young:AGE:MULTIPLIER`, `reward:MULTIPLIER` or `all:MULTIPLIER`.
+impl FromStr for StakeModRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts.as_slice() {
+            ["young", age, multiplier] => Ok(StakeModRule::Young {
+                age: number(age)?,
+                multiplier: number(multiplier)?,
+            }),
+            ["reward", multiplier] => Ok(StakeModRule::Reward {
+                multiplier: number(multiplier)?,
+            }),
+            ["all", multiplier] => Ok(StakeModRule::All {
+                multiplier: number(multiplier)?,
+            }),
+            _ => Err(format!("Unknown stake modifier {}.", s)),
+        }
+    }
+}
+
+/// The stake modifier rules of a scenario. An output's `stake_mod` is the
+/// product of every rule that matches it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct StakeMods {
+    rules: Vec<StakeModRule>,
+}
+
+impl StakeMods {
+    pub fn new(rules: Vec<StakeModRule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn rules(&self) -> &[StakeModRule] {
+        &self.rules
+    }
+
+    pub fn stake_mod(&self, denom: &Denom, block_height: u64) -> f64 {
+        self.rules
+            .iter()
+            .map(|p| p.multiplier(denom, block_height))
+            .product()
+    }
+
+    /// Whether a modifier depends on the output's age.
+    pub fn is_aged(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|p| matches!(p, StakeModRule::Young { .. }))
+    }
+}
+
+/// `weight` scaled by a stake modifier.
+pub fn modified_weight(weight: u64, stake_mod: f64) -> u64 {
+    if (stake_mod - 1.0).abs() < f64::EPSILON {
+        weight
+    } else {
+        (weight as f64 * stake_mod.max(0.0)) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+
+    fn output(coins: u64, created_height: u64) -> Denom {
+        Denom::builder()
+            .value(Amount::from_coins(coins))
+            .mature(true)
+            .created_height(created_height)
+            .build()
+    }
+
+    #[test]
+    fn test_rules_on_one_output() {
+        let denom = output(1_000, 0);
+        let bracket: WeightRule = "bracket".parse().unwrap();
+        assert_eq!(bracket.weight(&denom, 0), denom.weight());
+        assert_eq!(
+            "linear".parse::<WeightRule>().unwrap().weight(&denom, 0),
+            denom.value().to_sat()
+        );
+        assert_eq!(
+            "capped:100"
+                .parse::<WeightRule>()
+                .unwrap()
+                .weight(&denom, 0),
+            100 * amount::COIN
+        );
+        assert!("log".parse::<WeightRule>().unwrap().weight(&denom, 0) < denom.weight());
+        assert!("capped".parse::<WeightRule>().is_err());
+    }
+
+    #[test]
+    fn test_log_rewards_splitting() {
+        let rule = LogWeight;
+        let whole = rule.weight(&output(1_000, 0), 0);
+        let halves = 2 * rule.weight(&output(500, 0), 0);
+        assert!(halves > whole);
+    }
+
+    #[test]
+    fn test_coin_days_window() {
+        let rule: WeightRule = "coin-days:100:1_100".parse().unwrap();
+        let denom = output(1_000, 0);
+        assert!(rule.resets_on_stake());
+        assert_eq!(rule.weight(&denom, 99), 0);
+        assert_eq!(rule.weight(&denom, 100), 0);
+        assert_eq!(rule.weight(&denom, 600), denom.value().to_sat() / 2);
+        assert_eq!(rule.weight(&denom, 1_100), denom.value().to_sat());
+        assert_eq!(rule.weight(&denom, 9_000), denom.value().to_sat());
+    }
+
+    #[test]
+    fn test_stake_mods_multiply() {
+        let mods = StakeMods::new(vec![
+            "young:100:0.5".parse().unwrap(),
+            "reward:0.8".parse().unwrap(),
+        ]);
+        let reward = Denom::new(Amount::from_coins(1_000), 1.0, true, true, 1_000, 1_030);
+        assert!(mods.is_aged());
+        assert!((mods.stake_mod(&reward, 1_050) - 0.4).abs() < 1e-9);
+        assert!((mods.stake_mod(&reward, 1_100) - 0.8).abs() < 1e-9);
+        assert!((mods.stake_mod(&output(1_000, 0), 1_100) - 1.0).abs() < 1e-9);
+        assert_eq!(modified_weight(1_000, 0.25), 250);
+        assert!("young:100".parse::<StakeModRule>().is_err());
+    }
+
+    #[test]
+    fn test_coin_age_grows_to_full_weight() {
+        let rule: WeightRule = "coin-age:1_000".parse().unwrap();
+        let denom = output(1_000, 100);
+        assert!(rule.is_aged());
+        assert!(rule.weight(&denom, 200) < rule.weight(&denom, 600));
+        assert_eq!(rule.weight(&denom, 1_100), denom.weight());
+        assert_eq!(rule.weight(&denom, 5_000), denom.weight());
+
+        let rule = rule.with_bracket_mod(0.5).unwrap();
+        assert_eq!(rule, "coin-age:1_000:0.5".parse().unwrap());
+        assert_eq!(rule.weight(&denom, 1_100), denom.bracket_weight(0.5));
+        assert!(rule.weight(&denom, 1_100) < denom.weight());
+    }
+
+    #[test]
+    fn test_bracket_mod_needs_bracket_rule() {
+        let rule = WeightRule::default().with_bracket_mod(0.5).unwrap();
+        assert_eq!(rule, "bracket:0.5".parse().unwrap());
+        for rule in &["linear", "log", "capped:1_000", "coin-days:100:1_000"] {
+            let rule: WeightRule = rule.parse().unwrap();
+            assert!(matches!(
+                rule.with_bracket_mod(0.5),
+                Err(SimError::InvalidConfig(_))
+            ));
+        }
+    }
+}
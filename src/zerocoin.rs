@@ -0,0 +1,187 @@
+// Copyright 2020 Mimir (mimirmim)
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::amount::{self, Amount};
+use crate::denom::DenomStrategy;
+use crate::parse::number;
+use crate::staker::Staker;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Zerocoin staking as Veil launched with it. Only minted zerocoins of the
+/// fixed `DenomValue`s stake. Minting basecoin costs `mint_fee` per
+/// zerocoin, and a mint can't stake until an accumulator checkpoint at least
+/// `confirmations` blocks later has taken it in. A staked zerocoin is spent
+/// and reminted, so it waits for a checkpoint again.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ZerocoinModel {
+    pub mint_fee: Amount,
+    /// Blocks between accumulator checkpoints.
+    pub checkpoint_interval: u64,
+    pub confirmations: u64,
+}
+
+impl Default for ZerocoinModel {
+    fn default() -> Self {
+        Self {
+            mint_fee: Amount::COIN / 100,
+            checkpoint_interval: 10,
+            confirmations: 20,
+        }
+    }
+}
+
+impl ZerocoinModel {
+    /// Height a mint made at `block_height` can stake from.
+    pub fn mint_maturity(&self, block_height: u64) -> u64 {
+        let interval = self.checkpoint_interval.max(1);
+        (block_height + self.confirmations).div_ceil(interval) * interval
+    }
+}
+
+/// Parses `default` or `MINT_FEE:CHECKPOINT_INTERVAL:CONFIRMATIONS`, the fee
+/// in coins.
+impl FromStr for ZerocoinModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "default" {
+            return Ok(Self::default());
+        }
+
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts.as_slice() {
+            [mint_fee, checkpoint_interval, confirmations] => {
+                let checkpoint_interval = number(checkpoint_interval)?;
+                if checkpoint_interval == 0 {
+                    return Err(format!(
+                        "Checkpoint interval must be at least 1, got {}.",
+                        s
+                    ));
+                }
+                Ok(Self {
+                    mint_fee: number(mint_fee)?,
+                    checkpoint_interval,
+                    confirmations: number(confirmations)?,
+                })
+            }
+            _ => Err(format!(
+                "Expected default or MINT_FEE:CHECKPOINT_INTERVAL:CONFIRMATIONS, got {}.",
+                s
+            )),
+        }
+    }
+}
+
+/// What minting cost the stakers of one strategy.
+#[derive(Debug, Serialize)]
+pub struct ZerocoinRow {
+    pub denom_strat: DenomStrategy,
+    pub stakers: usize,
+    pub mints: u64,
+    pub mint_fees: Amount,
+    pub rewards: Amount,
+}
+
+impl ZerocoinRow {
+    /// Mint fees against the rewards they earned.
+    pub fn fee_share(&self) -> f64 {
+        if self.rewards.is_zero() {
+            0.0
+        } else {
+            self.mint_fees.to_sat() as f64 / self.rewards.to_sat() as f64
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZerocoinReport {
+    pub rows: Vec<ZerocoinRow>,
+}
+
+impl ZerocoinReport {
+    pub fn from_stakers(stakers: &[Staker]) -> Self {
+        let mut rows: BTreeMap<DenomStrategy, ZerocoinRow> = BTreeMap::new();
+        for staker in stakers {
+            let row = rows
+                .entry(staker.denom_strat())
+                .or_insert_with(|| ZerocoinRow {
+                    denom_strat: staker.denom_strat(),
+                    stakers: 0,
+                    mints: 0,
+                    mint_fees: Amount::ZERO,
+                    rewards: Amount::ZERO,
+                });
+            row.stakers += 1;
+            row.mints += staker.denoms().mint_count();
+            row.mint_fees += staker.denoms().mint_fees();
+            row.rewards += staker.rewards_earned();
+        }
+        Self {
+            rows: rows.into_values().collect(),
+        }
+    }
+}
+
+impl fmt::Display for ZerocoinReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>18} {:>8} {:>10} {:>12} {:>12} {:>8}",
+            "strategy", "stakers", "mints", "mint fees", "rewards", "fee%"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:>18} {:>8} {:>10} {:>12.2} {:>12} {:>7.3}%",
+                format!("{:?}", row.denom_strat),
+                row.stakers,
+                row.mints,
+                row.mint_fees.to_coins(),
+                row.rewards.to_sat() / amount::COIN,
+                row.fee_share() * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mints_wait_for_checkpoint() {
+        let model: ZerocoinModel = "0.01:10:20".parse().unwrap();
+        assert_eq!(model, ZerocoinModel::default());
+        assert_eq!(model.mint_maturity(100), 120);
+        assert_eq!(model.mint_maturity(101), 130);
+        assert!("0.01:0:20".parse::<ZerocoinModel>().is_err());
+        assert!("0.01:10.5:20".parse::<ZerocoinModel>().is_err());
+        assert!("0.000000001:10:20".parse::<ZerocoinModel>().is_err());
+        assert!("300000001:10:20".parse::<ZerocoinModel>().is_err());
+    }
+}